@@ -0,0 +1,163 @@
+//! Per-route allow-lists of source networks
+use crate::metrics::Metrics;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::MethodRouter,
+};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tracing::warn;
+
+/// Allow-list of one route
+#[derive(Clone)]
+struct SourceFilter {
+    route: &'static str,
+    allow: Arc<[IpNet]>,
+    trusted_proxies: Arc<[IpNet]>,
+    metrics: Arc<Metrics>,
+}
+
+/// Reject requests to `route` from outside `allow` with 403, before any other
+/// layer or the handler sees them. Nothing is checked when `allow` is empty.
+pub fn restrict<S>(
+    route: MethodRouter<S>,
+    name: &'static str,
+    allow: &[IpNet],
+    trusted_proxies: &[IpNet],
+    metrics: Arc<Metrics>,
+) -> MethodRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    if allow.is_empty() {
+        return route;
+    }
+    let filter = SourceFilter {
+        route: name,
+        allow: allow.into(),
+        trusted_proxies: trusted_proxies.into(),
+        metrics,
+    };
+    route.layer(middleware::from_fn_with_state(filter, check_source))
+}
+
+async fn check_source(
+    State(filter): State<SourceFilter>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let client = peer.and_then(|peer| client_ip(peer, request.headers(), &filter.trusted_proxies));
+    if client.is_some_and(|ip| contains(&filter.allow, ip)) {
+        return next.run(request).await;
+    }
+
+    warn!(
+        "Rejecting {} request from {} (peer {})",
+        filter.route,
+        client.map_or_else(|| "unknown address".to_string(), |ip| ip.to_string()),
+        peer.map_or_else(|| "unknown".to_string(), |ip| ip.to_string())
+    );
+    filter.metrics.record_rejected_source(filter.route);
+    (
+        StatusCode::FORBIDDEN,
+        "Forbidden: source address not allowed",
+    )
+        .into_response()
+}
+
+/// Address of the client: the peer itself, or for a trusted proxy the last address
+/// of `X-Forwarded-For` not added by a trusted proxy. `None` when the header holds
+/// something other than addresses.
+fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpNet]) -> Option<IpAddr> {
+    let mut client = peer.to_canonical();
+    if !contains(trusted_proxies, client) {
+        return Some(client);
+    }
+
+    let mut forwarded = Vec::new();
+    for value in headers.get_all("X-Forwarded-For") {
+        forwarded.extend(value.to_str().ok()?.split(','));
+    }
+    // Each proxy appends the address it received the request from
+    for entry in forwarded.iter().rev() {
+        client = entry.trim().parse::<IpAddr>().ok()?.to_canonical();
+        if !contains(trusted_proxies, client) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+fn contains(networks: &[IpNet], ip: IpAddr) -> bool {
+    networks.iter().any(|network| network.contains(&ip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn networks(networks: &[&str]) -> Vec<IpNet> {
+        networks.iter().map(|n| n.parse().unwrap()).collect()
+    }
+
+    fn forwarded_for(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append("X-Forwarded-For", value.parse().unwrap());
+        }
+        headers
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn test_client_ip_untrusted_peer() {
+        // Headers from untrusted peers are ignored
+        let headers = forwarded_for(&["10.0.0.1"]);
+        assert_eq!(
+            client_ip(ip("203.0.113.9"), &headers, &[]),
+            Some(ip("203.0.113.9"))
+        );
+        assert_eq!(
+            client_ip(ip("::ffff:203.0.113.9"), &headers, &[]),
+            Some(ip("203.0.113.9"))
+        );
+    }
+
+    #[test]
+    fn test_client_ip_trusted_proxies() {
+        let trusted = networks(&["127.0.0.1/32", "10.1.0.0/16"]);
+
+        let headers = forwarded_for(&["192.0.2.1, 203.0.113.9", "10.1.2.3"]);
+        assert_eq!(
+            client_ip(ip("127.0.0.1"), &headers, &trusted),
+            Some(ip("203.0.113.9"))
+        );
+
+        // Only trusted proxies are all the way back
+        let headers = forwarded_for(&["10.1.0.7"]);
+        assert_eq!(
+            client_ip(ip("127.0.0.1"), &headers, &trusted),
+            Some(ip("10.1.0.7"))
+        );
+
+        // No header: the proxy itself
+        assert_eq!(
+            client_ip(ip("127.0.0.1"), &HeaderMap::new(), &trusted),
+            Some(ip("127.0.0.1"))
+        );
+
+        let headers = forwarded_for(&["unknown"]);
+        assert_eq!(client_ip(ip("127.0.0.1"), &headers, &trusted), None);
+    }
+}
@@ -0,0 +1,80 @@
+//! Per-route request limits: body size, content type and handling time
+use crate::config::RouteConfig;
+use axum::{
+    extract::{DefaultBodyLimit, Request},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::MethodRouter,
+};
+use std::convert::Infallible;
+use std::time::Duration;
+use tower_http::limit::RequestBodyLimitLayer;
+use tower_http::timeout::TimeoutLayer;
+use tracing::warn;
+
+/// Wrap `route` in the limits of `config`: bodies over `max_body_bytes` get 413
+/// and requests taking longer than `timeout_secs` get 408. With `json`, requests
+/// whose `Content-Type` is not JSON get 415 before the body is read.
+pub fn apply<S>(route: MethodRouter<S>, config: &RouteConfig, json: bool) -> MethodRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let route = route
+        .layer::<_, Infallible>(TimeoutLayer::new(Duration::from_secs(config.timeout_secs)))
+        .layer::<_, Infallible>(RequestBodyLimitLayer::new(config.max_body_bytes))
+        // Replaces axum's built-in 2 MiB limit of the body extractors
+        .layer::<_, Infallible>(DefaultBodyLimit::disable());
+    if json {
+        route.layer(middleware::from_fn(require_json))
+    } else {
+        route
+    }
+}
+
+/// Reject requests without an `application/json` (or `+json`) content type
+async fn require_json(request: Request, next: Next) -> Response {
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+    if content_type.is_some_and(is_json) {
+        return next.run(request).await;
+    }
+
+    warn!(
+        "Rejecting request with content type {:?}",
+        content_type.unwrap_or("")
+    );
+    (
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        "Expected Content-Type: application/json",
+    )
+        .into_response()
+}
+
+fn is_json(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or("").trim();
+    let Some((kind, subtype)) = essence.split_once('/') else {
+        return false;
+    };
+    kind.eq_ignore_ascii_case("application")
+        && (subtype.eq_ignore_ascii_case("json") || subtype.to_ascii_lowercase().ends_with("+json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_json() {
+        assert!(is_json("application/json"));
+        assert!(is_json("application/json; charset=utf-8"));
+        assert!(is_json("Application/JSON"));
+        assert!(is_json("application/vnd.gitcode+json"));
+        assert!(!is_json("text/plain"));
+        assert!(!is_json("application/x-www-form-urlencoded"));
+        assert!(!is_json("json"));
+        assert!(!is_json(""));
+    }
+}
@@ -1,4 +1,6 @@
+pub mod allow;
 pub mod handlers;
+pub mod limits;
 pub mod routes;
 
 pub use routes::{create_router, AppState};
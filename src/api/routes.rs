@@ -1,27 +1,79 @@
+use crate::api::{allow, limits};
+use crate::audit::{AuditEntry, AuditLog, AuditQuery};
+use crate::config::{RouteConfig, ServerConfig};
 use crate::crater::WebhookCallback;
+use crate::error::BotError;
+use crate::metrics::Metrics;
+use crate::request_id;
 use crate::webhook::{CallbackHandler, WebhookReceiver};
 use axum::{
     body::Bytes,
-    extract::State,
-    http::{HeaderMap, StatusCode},
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::IntoResponse,
     routing::{get, post},
     Json, Router,
 };
 use std::sync::Arc;
-use tracing::{error, warn};
+use tokio_util::task::TaskTracker;
+use tracing::{error, warn, Instrument, Span};
 
 #[derive(Clone)]
 pub struct AppState {
     pub webhook_receiver: Arc<WebhookReceiver>,
     pub callback_handler: Arc<CallbackHandler>,
     pub callback_secret: String,
+    pub audit_log: Arc<AuditLog>,
+    /// Bearer token for `GET /audit`; the endpoint is disabled when empty
+    pub audit_query_token: String,
+    pub metrics: Arc<Metrics>,
+    /// Webhook processing tasks, waited for on shutdown
+    pub tasks: TaskTracker,
 }
 
-pub fn create_router(state: AppState) -> Router {
+pub fn create_router(state: AppState, server: &ServerConfig) -> Router {
+    // The allow-list is checked first, before signatures or tokens
+    let route = |method_router, name, config: &RouteConfig, json| {
+        allow::restrict(
+            limits::apply(method_router, config, json),
+            name,
+            &config.allow,
+            &server.trusted_proxies,
+            state.metrics.clone(),
+        )
+    };
+    let routes = &server.routes;
+
     Router::new()
         .route("/health", get(health_check))
-        .route("/webhook/gitcode", post(gitcode_webhook_handler))
-        .route("/callback/crater", post(crater_callback_handler))
+        .route(
+            "/webhook/gitcode",
+            route(
+                post(gitcode_webhook_handler),
+                "webhook",
+                &routes.webhook,
+                true,
+            ),
+        )
+        .route(
+            "/callback/crater",
+            route(
+                post(crater_callback_handler),
+                "callback",
+                &routes.callback,
+                true,
+            ),
+        )
+        .route(
+            "/audit",
+            route(get(audit_query_handler), "audit", &routes.audit, false),
+        )
+        .route(
+            "/metrics",
+            route(get(metrics_handler), "metrics", &routes.metrics, false),
+        )
+        .layer(middleware::from_fn(request_id::propagate))
         .with_state(state)
 }
 
@@ -29,6 +81,13 @@ async fn health_check() -> &'static str {
     "OK"
 }
 
+async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
+
 async fn gitcode_webhook_handler(
     State(state): State<AppState>,
     headers: HeaderMap,
@@ -38,13 +97,23 @@ async fn gitcode_webhook_handler(
     let signature = headers
         .get("X-GitCode-Token")
         .and_then(|h| h.to_str().ok())
-        .unwrap_or("");
+        .unwrap_or("")
+        .to_string();
 
-    // Handle the webhook with raw payload for signature verification
-    state
-        .webhook_receiver
-        .handle_gitcode_webhook(&payload, signature)
-        .await
+    // Handle the webhook with raw payload for signature verification. It runs in a
+    // task of its own so that a route timeout only cuts the response short, instead
+    // of stopping a command between its crater and platform calls.
+    let receiver = state.webhook_receiver.clone();
+    let id = request_id::current().unwrap_or_else(request_id::generate);
+    let task = state.tasks.spawn(
+        request_id::scope(id, async move {
+            receiver.handle_gitcode_webhook(&payload, &signature).await
+        })
+        .instrument(Span::current()),
+    );
+    task.await
+        .map_err(|e| BotError::Internal(format!("Webhook task failed: {}", e)))
+        .and_then(|result| result)
         .map_err(|e| {
             error!("Failed to handle webhook: {}", e);
             (
@@ -98,3 +167,41 @@ async fn crater_callback_handler(
     }
 }
 
+async fn audit_query_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEntry>>, (StatusCode, String)> {
+    if state.audit_query_token.is_empty() || !state.audit_log.is_enabled() {
+        return Err((StatusCode::NOT_FOUND, "Audit log query is disabled".to_string()));
+    }
+
+    let auth_header = headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("");
+    let expected_auth = format!("Bearer {}", state.audit_query_token);
+
+    use subtle::ConstantTimeEq;
+    if !bool::from(auth_header.as_bytes().ct_eq(expected_auth.as_bytes())) {
+        warn!("Audit query authentication failed - unauthorized request");
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Unauthorized: Invalid or missing authentication".to_string(),
+        ));
+    }
+
+    // Reading the rotated files can take a while; keep it off the async workers
+    let audit_log = state.audit_log.clone();
+    let entries = tokio::task::spawn_blocking(move || audit_log.query(&query))
+        .await
+        .map_err(|e| BotError::Internal(format!("Audit query task failed: {}", e)))
+        .and_then(|result| result);
+    entries.map(Json).map_err(|e| {
+        error!("Failed to query audit log: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to query audit log".to_string(),
+        )
+    })
+}
@@ -0,0 +1,377 @@
+//! Append-only audit trail of bot commands and administrative actions
+use crate::config::AuditConfig;
+use crate::error::Result;
+use crate::platforms::Thread;
+use crate::request_id;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::error;
+
+/// Whether a command was allowed to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// Not decided yet, e.g. processing failed before the checks
+    Pending,
+    Allowed,
+    RateLimited,
+    /// The comment could not be parsed into a command
+    Rejected,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub platform: String,
+    pub project: String,
+    /// Issue or merge request number
+    pub issue_id: u64,
+    #[serde(default)]
+    pub merge_request: bool,
+    pub author: String,
+    pub comment: String,
+    /// Parsed command, if parsing succeeded
+    pub command: Option<String>,
+    pub decision: Decision,
+    /// Crater API calls made while handling the command, e.g. `create_experiment user--repo-1`
+    pub crater_calls: Vec<String>,
+    /// Whether handling succeeded
+    pub success: bool,
+    pub error: Option<String>,
+    /// ID shown to the user in place of the error's details
+    #[serde(default)]
+    pub correlation_id: Option<String>,
+    /// ID of the webhook request that carried the command
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn new(platform: &str, project: &str, thread: Thread, author: &str, comment: &str) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            platform: platform.to_string(),
+            project: project.to_string(),
+            issue_id: thread.number(),
+            merge_request: thread.is_merge_request(),
+            author: author.to_string(),
+            comment: comment.to_string(),
+            command: None,
+            decision: Decision::Pending,
+            crater_calls: Vec::new(),
+            success: false,
+            error: None,
+            correlation_id: None,
+            request_id: request_id::current(),
+        }
+    }
+
+    pub fn crater_call(&mut self, call: impl Into<String>) {
+        self.crater_calls.push(call.into());
+    }
+}
+
+/// Filters for [`AuditLog::query`]; unset fields match everything
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditQuery {
+    pub project: Option<String>,
+    pub author: Option<String>,
+    pub issue_id: Option<u64>,
+    /// Maximum number of entries, newest first
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.project.as_ref().is_none_or(|p| *p == entry.project)
+            && self.author.as_ref().is_none_or(|a| *a == entry.author)
+            && self.issue_id.is_none_or(|i| i == entry.issue_id)
+    }
+}
+
+const DEFAULT_QUERY_LIMIT: usize = 100;
+
+struct Writer {
+    file: File,
+    size: u64,
+}
+
+/// JSON-lines audit log with size-based rotation (`audit.log`, `audit.log.1`, ...)
+pub struct AuditLog {
+    config: AuditConfig,
+    writer: Mutex<Option<Writer>>,
+}
+
+impl AuditLog {
+    pub fn new(config: AuditConfig) -> Result<Self> {
+        let writer = if config.enabled {
+            Some(open_writer(Path::new(&config.path))?)
+        } else {
+            None
+        };
+        Ok(Self {
+            config,
+            writer: Mutex::new(writer),
+        })
+    }
+
+    /// An audit log that records nothing
+    pub fn disabled() -> Self {
+        Self {
+            config: AuditConfig::default(),
+            writer: Mutex::new(None),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Append an entry. Failures are logged rather than failing the command.
+    pub fn record(&self, entry: &AuditEntry) {
+        if let Err(e) = self.try_record(entry) {
+            error!("Failed to write audit entry: {}", e);
+        }
+    }
+
+    fn try_record(&self, entry: &AuditEntry) -> Result<()> {
+        let mut guard = self.writer.lock().unwrap();
+        let Some(writer) = guard.as_mut() else {
+            return Ok(());
+        };
+
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        if writer.size > 0 && writer.size + line.len() as u64 > self.config.max_bytes {
+            self.rotate()?;
+            *writer = open_writer(Path::new(&self.config.path))?;
+        }
+
+        writer.file.write_all(&line)?;
+        writer.file.flush()?;
+        writer.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Shift `path.N` to `path.N+1`, dropping the oldest, and move `path` to `path.1`
+    fn rotate(&self) -> Result<()> {
+        let path = Path::new(&self.config.path);
+        let max_files = self.config.max_files.max(1);
+
+        let oldest = rotated_path(path, max_files);
+        if oldest.exists() {
+            fs::remove_file(&oldest)?;
+        }
+        for n in (1..max_files).rev() {
+            let from = rotated_path(path, n);
+            if from.exists() {
+                fs::rename(&from, rotated_path(path, n + 1))?;
+            }
+        }
+        fs::rename(path, rotated_path(path, 1))?;
+        Ok(())
+    }
+
+    /// Matching entries across the current and rotated files, newest first.
+    /// Reads the files from disk, so call it off the async runtime.
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>> {
+        if !self.config.enabled {
+            return Ok(Vec::new());
+        }
+
+        let limit = query.limit.unwrap_or(DEFAULT_QUERY_LIMIT);
+        let mut results = Vec::new();
+        for (file, len) in self.snapshot()? {
+            let mut entries = read_entries(file.take(len))?;
+            entries.reverse();
+            for entry in entries.into_iter().filter(|e| query.matches(e)) {
+                results.push(entry);
+                if results.len() >= limit {
+                    return Ok(results);
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    /// Open the current and rotated files, newest first, with the length written so far.
+    /// The writer lock is held only while opening them: the open handles keep reading
+    /// the same files if a rotation renames them, and entries appended afterwards are
+    /// past the recorded length.
+    fn snapshot(&self) -> Result<Vec<(File, u64)>> {
+        let _guard = self.writer.lock().unwrap();
+        let path = Path::new(&self.config.path);
+        let paths = std::iter::once(path.to_path_buf())
+            .chain((1..=self.config.max_files.max(1)).map(|n| rotated_path(path, n)));
+
+        let mut files = Vec::new();
+        for path in paths {
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let len = file.metadata()?.len();
+            files.push((file, len));
+        }
+        Ok(files)
+    }
+}
+
+fn open_writer(path: &Path) -> Result<Writer> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok(Writer { file, size })
+}
+
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+fn read_entries(file: impl Read) -> Result<Vec<AuditEntry>> {
+    let reader = BufReader::new(file);
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        // Skip lines torn by a crash rather than failing the whole query
+        if let Ok(entry) = serde_json::from_str(&line) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_config(name: &str, max_bytes: u64) -> AuditConfig {
+        let dir = std::env::temp_dir().join(format!(
+            "crater-ohos-bot-audit-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        AuditConfig {
+            enabled: true,
+            path: dir.join("audit.log").to_string_lossy().into_owned(),
+            max_bytes,
+            max_files: 2,
+            query_token: String::new(),
+        }
+    }
+
+    fn entry(project: &str, issue_id: u64, author: &str) -> AuditEntry {
+        let mut entry = AuditEntry::new(
+            "gitcode",
+            project,
+            Thread::Issue(issue_id),
+            author,
+            "@crater-bot abort",
+        );
+        entry.command = Some("Abort".to_string());
+        entry.decision = Decision::Allowed;
+        entry.crater_call("abort_experiment org--repo-1");
+        entry.success = true;
+        entry
+    }
+
+    #[test]
+    fn test_record_and_query() {
+        let log = AuditLog::new(temp_config("query", 1024 * 1024)).unwrap();
+        log.record(&entry("org/a", 1, "alice"));
+        log.record(&entry("org/b", 2, "bob"));
+        log.record(&entry("org/a", 3, "carol"));
+
+        let all = log.query(&AuditQuery::default()).unwrap();
+        assert_eq!(
+            all.iter().map(|e| e.issue_id).collect::<Vec<_>>(),
+            vec![3, 2, 1]
+        );
+
+        let by_project = log
+            .query(&AuditQuery {
+                project: Some("org/a".to_string()),
+                limit: Some(1),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(by_project.len(), 1);
+        assert_eq!(by_project[0].author, "carol");
+        assert_eq!(
+            by_project[0].crater_calls,
+            vec!["abort_experiment org--repo-1"]
+        );
+    }
+
+    #[test]
+    fn test_rotation() {
+        let line_len = serde_json::to_vec(&entry("org/a", 1, "alice"))
+            .unwrap()
+            .len() as u64
+            + 1;
+        let config = temp_config("rotation", line_len * 2);
+        let path = PathBuf::from(&config.path);
+        let log = AuditLog::new(config).unwrap();
+
+        for issue_id in 1..=7 {
+            log.record(&entry("org/a", issue_id, "alice"));
+        }
+
+        assert!(rotated_path(&path, 1).exists());
+        assert!(rotated_path(&path, 2).exists());
+        assert!(!rotated_path(&path, 3).exists());
+
+        // Only the current file and two rotated files are kept
+        let issues: Vec<u64> = log
+            .query(&AuditQuery::default())
+            .unwrap()
+            .iter()
+            .map(|e| e.issue_id)
+            .collect();
+        assert_eq!(issues, vec![7, 6, 5, 4, 3]);
+    }
+
+    #[test]
+    fn test_snapshot_survives_rotation() {
+        let line_len = serde_json::to_vec(&entry("org/a", 1, "alice"))
+            .unwrap()
+            .len() as u64
+            + 1;
+        let log = AuditLog::new(temp_config("snapshot", line_len * 2)).unwrap();
+        log.record(&entry("org/a", 1, "alice"));
+        log.record(&entry("org/a", 2, "alice"));
+
+        let snapshot = log.snapshot().unwrap();
+        // Rotates the snapshotted file to audit.log.1 and starts a new one
+        log.record(&entry("org/a", 3, "alice"));
+
+        let issues: Vec<u64> = snapshot
+            .into_iter()
+            .flat_map(|(file, len)| read_entries(file.take(len)).unwrap())
+            .map(|e| e.issue_id)
+            .collect();
+        assert_eq!(issues, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_disabled_log_records_nothing() {
+        let log = AuditLog::disabled();
+        log.record(&entry("org/a", 1, "alice"));
+        assert!(log.query(&AuditQuery::default()).unwrap().is_empty());
+    }
+}
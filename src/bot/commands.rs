@@ -1,59 +1,335 @@
-use crate::error::{BotError, Result};
+use crate::bot::grammar::{tokenize, Arguments, Expected, OptionArg, ParseError, ParseErrorKind};
+use crate::bot::registry::{self, CommandSpec};
+use crate::error::Result;
+use crate::i18n::Locale;
+
+/// Experiment settings given as options, overriding the configured defaults
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExperimentOptions {
+    /// `mode=<mode>`, e.g. `check-only`
+    pub mode: Option<String>,
+    /// `crates=<selection>`, e.g. `"list:serde, rand"`
+    pub crates: Option<String>,
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BotCommand {
     Run {
         toolchains: Vec<String>,
+        options: ExperimentOptions,
     },
+    /// `run pr`: the configured base toolchain against the merge request's build
+    RunPr { options: ExperimentOptions },
+    /// `try`: the merge request's base commit against its merge (or head) commit
+    Try { options: ExperimentOptions },
     Status,
     Abort,
     Help,
+    /// `help <command>`: detailed help for one registered command
+    HelpTopic { command: &'static str },
     List,
 }
 
+/// A command line: the command and the reply language it asked for with `lang=`
+#[derive(Debug)]
+pub struct Invocation {
+    pub command: Result<BotCommand>,
+    /// Also set when the command itself is invalid, so the error is localized
+    pub lang: Option<Locale>,
+}
+
 impl BotCommand {
-    pub fn parse(text: &str, trigger_prefix: &str) -> Result<Option<Self>> {
-        let text = text.trim();
-        
-        // Check if this is a bot command
-        if !text.starts_with(trigger_prefix) {
-            return Ok(None);
+    /// Name of the command in the registry, e.g. `run pr`
+    pub fn name(&self) -> &'static str {
+        match self {
+            BotCommand::Run { .. } => "run",
+            BotCommand::RunPr { .. } => "run pr",
+            BotCommand::Try { .. } => "try",
+            BotCommand::Status => "status",
+            BotCommand::Abort => "abort",
+            BotCommand::Help | BotCommand::HelpTopic { .. } => "help",
+            BotCommand::List => "list",
         }
+    }
+
+    /// Parse the first command in `text`, see [`BotCommand::parse_all`]
+    pub fn parse(text: &str, trigger_prefix: &str) -> Result<Option<Self>> {
+        Self::parse_all(text, trigger_prefix)
+            .into_iter()
+            .next()
+            .transpose()
+    }
 
-        // Remove the trigger prefix
-        let command_text = text[trigger_prefix.len()..].trim();
-        
-        if command_text.is_empty() {
-            return Ok(Some(BotCommand::Help));
+    /// Parse every line of `text` that starts with `trigger_prefix`, in order.
+    /// Lines inside fenced code blocks and quoted lines (`> ...`) are skipped.
+    pub fn parse_all(text: &str, trigger_prefix: &str) -> Vec<Result<Self>> {
+        Self::parse_invocations(text, trigger_prefix)
+            .into_iter()
+            .map(|invocation| invocation.command)
+            .collect()
+    }
+
+    /// Like [`BotCommand::parse_all`], keeping the `lang=` option of each line
+    pub fn parse_invocations(text: &str, trigger_prefix: &str) -> Vec<Invocation> {
+        command_lines(text, trigger_prefix)
+            .into_iter()
+            .map(|line| {
+                let mut lang = None;
+                let command = Self::parse_line(line, trigger_prefix, &mut lang).map_err(Into::into);
+                Invocation { command, lang }
+            })
+            .collect()
+    }
+
+    /// Parse a command line starting with `trigger_prefix`. The global `lang`
+    /// option is stored in `lang` before the command itself is checked.
+    fn parse_line(
+        line: &str,
+        trigger_prefix: &str,
+        lang: &mut Option<Locale>,
+    ) -> std::result::Result<Self, ParseError> {
+        let tokens = tokenize(line, trigger_prefix.len())?;
+
+        let Some((name, rest)) = tokens.split_first() else {
+            return Ok(BotCommand::Help);
+        };
+        let mut arguments = Arguments::parse(line, rest)?;
+        *lang = take_lang(line, &mut arguments.options)?;
+
+        match name.text.to_lowercase().as_str() {
+            "run" => match arguments.positional.as_slice() {
+                [pr] if pr.text.eq_ignore_ascii_case("pr") => Ok(BotCommand::RunPr {
+                    options: ExperimentOptions::parse(line, registry::command("run pr"), &arguments)?,
+                }),
+                [_, _, ..] => Ok(BotCommand::Run {
+                    options: ExperimentOptions::parse(line, registry::command("run"), &arguments)?,
+                    toolchains: arguments
+                        .positional
+                        .iter()
+                        .map(|token| token.text.clone())
+                        .collect(),
+                }),
+                _ => Err(ParseError::at(
+                    ParseErrorKind::MissingToolchains(registry::command("run").usage(trigger_prefix)),
+                    line,
+                    name,
+                )),
+            },
+            "try" => {
+                no_positional(line, &arguments)?;
+                Ok(BotCommand::Try {
+                    options: ExperimentOptions::parse(line, registry::command("try"), &arguments)?,
+                })
+            }
+            "help" if !arguments.positional.is_empty() => {
+                check_option_keys(line, registry::command("help"), &arguments.options)?;
+                let words = &arguments.positional;
+                let topic: Vec<&str> = words.iter().map(|token| token.text.as_str()).collect();
+                let topic = topic.join(" ");
+                match registry::find(&topic) {
+                    Some(spec) => Ok(BotCommand::HelpTopic { command: spec.name }),
+                    None => {
+                        let names: Vec<&str> = registry::COMMANDS.iter().map(|spec| spec.name).collect();
+                        let (first, last) = (&words[0], &words[words.len() - 1]);
+                        Err(ParseError::new(
+                            ParseErrorKind::UnknownCommand {
+                                suggestion: registry::suggest(&topic, &names),
+                                name: topic,
+                            },
+                            line,
+                            first.column,
+                            last.column + last.width - first.column,
+                        ))
+                    }
+                }
+            }
+            command @ ("status" | "abort" | "help" | "list") => {
+                no_positional(line, &arguments)?;
+                check_option_keys(line, registry::command(command), &arguments.options)?;
+                Ok(match command {
+                    "status" => BotCommand::Status,
+                    "abort" => BotCommand::Abort,
+                    "help" => BotCommand::Help,
+                    _ => BotCommand::List,
+                })
+            }
+            _ => Err(ParseError::at(
+                ParseErrorKind::UnknownCommand {
+                    name: name.text.clone(),
+                    suggestion: registry::suggest(&name.text, &registry::command_words()),
+                },
+                line,
+                name,
+            )),
         }
+    }
+}
+
+impl ExperimentOptions {
+    fn parse(
+        line: &str,
+        spec: &CommandSpec,
+        arguments: &Arguments,
+    ) -> std::result::Result<Self, ParseError> {
+        check_option_keys(line, spec, &arguments.options)?;
 
-        let parts: Vec<&str> = command_text.split_whitespace().collect();
-        
-        match parts[0].to_lowercase().as_str() {
-            "run" => {
-                if parts.len() < 3 {
-                    return Err(BotError::InvalidCommand(
-                        "run command requires at least two toolchain arguments. Usage: @crater-bot run <toolchain1> <toolchain2>".to_string()
-                    ));
+        let mut parsed = ExperimentOptions::default();
+        for option in &arguments.options {
+            let invalid = |expected: Expected, suggestion: Option<&'static str>| {
+                ParseError::new(
+                    ParseErrorKind::InvalidValue {
+                        option: option.key.clone(),
+                        value: option.value.clone(),
+                        expected,
+                        suggestion,
+                    },
+                    line,
+                    option.column,
+                    option.width,
+                )
+            };
+
+            match option.key.as_str() {
+                "mode" => {
+                    if !registry::MODES.contains(&option.value.as_str()) {
+                        return Err(invalid(
+                            Expected::OneOf(registry::MODES.join(", ")),
+                            registry::suggest(&option.value, registry::MODES),
+                        ));
+                    }
+                    parsed.mode = Some(option.value.clone());
+                }
+                "crates" => {
+                    if !registry::is_valid_crate_select(&option.value) {
+                        return Err(invalid(
+                            Expected::OneOf(format!(
+                                "{}, top-<n>, random-<n>, list:<crates>",
+                                registry::CRATE_SELECTS.join(", ")
+                            )),
+                            registry::suggest(&option.value, registry::CRATE_SELECTS),
+                        ));
+                    }
+                    parsed.crates = Some(option.value.clone());
                 }
-                let toolchains = parts[1..].iter().map(|s| s.to_string()).collect();
-                Ok(Some(BotCommand::Run { toolchains }))
+                _ => unreachable!("option keys are checked against the registry"),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Reject options `spec` does not accept and options given twice
+fn check_option_keys(
+    line: &str,
+    spec: &CommandSpec,
+    options: &[OptionArg],
+) -> std::result::Result<(), ParseError> {
+    let known: Vec<&'static str> = spec.options.iter().map(|option| option.key).collect();
+    // Global options are taken out before this check, but are still worth suggesting
+    let candidates: Vec<&'static str> = known
+        .iter()
+        .copied()
+        .chain(registry::GLOBAL_OPTIONS.iter().map(|option| option.key))
+        .collect();
+    for (i, option) in options.iter().enumerate() {
+        let kind = if !known.contains(&option.key.as_str()) {
+            ParseErrorKind::UnknownOption {
+                key: option.key.clone(),
+                suggestion: registry::suggest(&option.key, &candidates),
+            }
+        } else if options[..i].iter().any(|earlier| earlier.key == option.key) {
+            ParseErrorKind::DuplicateOption(option.key.clone())
+        } else {
+            continue;
+        };
+        return Err(ParseError::new(kind, line, option.column, option.width));
+    }
+    Ok(())
+}
+
+/// Remove the `lang` option from `options` and parse its value
+fn take_lang(
+    line: &str,
+    options: &mut Vec<OptionArg>,
+) -> std::result::Result<Option<Locale>, ParseError> {
+    let mut lang = None;
+    for option in options.iter().filter(|option| option.key == "lang") {
+        let error = |kind| ParseError::new(kind, line, option.column, option.width);
+        if lang.is_some() {
+            return Err(error(ParseErrorKind::DuplicateOption(option.key.clone())));
+        }
+        let codes: Vec<&'static str> = Locale::ALL.iter().map(|locale| locale.code()).collect();
+        match Locale::parse(&option.value) {
+            Some(locale) => lang = Some(locale),
+            None => {
+                return Err(error(ParseErrorKind::InvalidValue {
+                    option: option.key.clone(),
+                    value: option.value.clone(),
+                    expected: Expected::OneOf(codes.join(", ")),
+                    suggestion: registry::suggest(&option.value, &codes),
+                }))
             }
-            "status" => Ok(Some(BotCommand::Status)),
-            "abort" => Ok(Some(BotCommand::Abort)),
-            "help" => Ok(Some(BotCommand::Help)),
-            "list" => Ok(Some(BotCommand::List)),
-            _ => Err(BotError::InvalidCommand(format!(
-                "Unknown command: {}. Use 'help' to see available commands",
-                parts[0]
-            ))),
         }
     }
+    options.retain(|option| option.key != "lang");
+    Ok(lang)
+}
+
+fn no_positional(line: &str, arguments: &Arguments) -> std::result::Result<(), ParseError> {
+    match arguments.positional.first() {
+        Some(token) => Err(ParseError::at(
+            ParseErrorKind::UnexpectedArgument(token.text.clone()),
+            line,
+            token,
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Each line that starts with `trigger_prefix`, outside code blocks and quotes
+fn command_lines<'a>(text: &'a str, trigger_prefix: &str) -> Vec<&'a str> {
+    let mut lines = Vec::new();
+    // The fence that opened the current code block, e.g. "```" or "~~~~"
+    let mut fence: Option<&str> = None;
+
+    for line in text.lines() {
+        let line = line.trim();
+
+        if let Some(open) = fence {
+            if line.starts_with(open) && line[open.len()..].trim().is_empty() {
+                fence = None;
+            }
+            continue;
+        }
+        if let Some(open) = code_fence(line) {
+            fence = Some(open);
+            continue;
+        }
+        if line.starts_with('>') {
+            continue;
+        }
+
+        if let Some(rest) = line.strip_prefix(trigger_prefix) {
+            // `@crater-bot-dev` is a different account
+            if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+                lines.push(line);
+            }
+        }
+    }
+    lines
+}
+
+/// The opening fence of a Markdown code block (three or more backticks or tildes)
+fn code_fence(line: &str) -> Option<&str> {
+    let marker = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.chars().take_while(|c| *c == marker).count();
+    (len >= 3).then(|| &line[..len])
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::BotError;
 
     #[test]
     fn test_parse_run_command() {
@@ -61,7 +337,8 @@ mod tests {
         assert_eq!(
             cmd,
             Some(BotCommand::Run {
-                toolchains: vec!["stable".to_string(), "beta".to_string()]
+                toolchains: vec!["stable".to_string(), "beta".to_string()],
+                options: ExperimentOptions::default(),
             })
         );
     }
@@ -72,7 +349,30 @@ mod tests {
         assert_eq!(
             cmd,
             Some(BotCommand::Run {
-                toolchains: vec!["nightly-2024-01-01".to_string(), "stable".to_string()]
+                toolchains: vec!["nightly-2024-01-01".to_string(), "stable".to_string()],
+                options: ExperimentOptions::default(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_run_pr_command() {
+        let cmd = BotCommand::parse("@crater-bot run pr", "@crater-bot").unwrap();
+        assert_eq!(
+            cmd,
+            Some(BotCommand::RunPr {
+                options: ExperimentOptions::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_try_command() {
+        let cmd = BotCommand::parse("@crater-bot try", "@crater-bot").unwrap();
+        assert_eq!(
+            cmd,
+            Some(BotCommand::Try {
+                options: ExperimentOptions::default()
             })
         );
     }
@@ -118,4 +418,202 @@ mod tests {
         let result = BotCommand::parse("@crater-bot run stable", "@crater-bot");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_parse_command_on_later_line() {
+        let cmd = BotCommand::parse("Thanks for the fix!\n\n@crater-bot status\n", "@crater-bot").unwrap();
+        assert_eq!(cmd, Some(BotCommand::Status));
+    }
+
+    #[test]
+    fn test_parse_all_commands_in_order() {
+        let text = "@crater-bot run stable beta\nand then\n  @crater-bot list\n@crater-bot bogus";
+        let commands = BotCommand::parse_all(text, "@crater-bot");
+        assert_eq!(commands.len(), 3);
+        assert_eq!(
+            commands[0].as_ref().unwrap(),
+            &BotCommand::Run {
+                toolchains: vec!["stable".to_string(), "beta".to_string()],
+                options: ExperimentOptions::default(),
+            }
+        );
+        assert_eq!(commands[1].as_ref().unwrap(), &BotCommand::List);
+        assert!(commands[2].is_err());
+    }
+
+    #[test]
+    fn test_parse_skips_code_blocks_and_quotes() {
+        let text = "> @crater-bot abort\n\
+                    ```\n@crater-bot run stable beta\n```\n\
+                    ~~~~text\n@crater-bot list\n```\n~~~~\n\
+                    @crater-bot status";
+        let commands = BotCommand::parse_all(text, "@crater-bot");
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].as_ref().unwrap(), &BotCommand::Status);
+    }
+
+    #[test]
+    fn test_parse_requires_whole_prefix() {
+        let cmd = BotCommand::parse("@crater-bot-dev status", "@crater-bot").unwrap();
+        assert_eq!(cmd, None);
+    }
+
+    fn parse_error(text: &str) -> ParseError {
+        match BotCommand::parse(text, "@crater-bot") {
+            Err(BotError::Parse(err)) => err,
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_run_with_options() {
+        let cmd = BotCommand::parse(
+            r#"@crater-bot run "nightly+rustflags=-C opt-level=3" stable --mode check-only crates="list:serde, rand""#,
+            "@crater-bot",
+        )
+        .unwrap();
+        assert_eq!(
+            cmd,
+            Some(BotCommand::Run {
+                toolchains: vec![
+                    "nightly+rustflags=-C opt-level=3".to_string(),
+                    "stable".to_string()
+                ],
+                options: ExperimentOptions {
+                    mode: Some("check-only".to_string()),
+                    crates: Some("list:serde, rand".to_string()),
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_try_with_options() {
+        let cmd = BotCommand::parse("@crater-bot try --mode=build-only", "@crater-bot").unwrap();
+        assert_eq!(
+            cmd,
+            Some(BotCommand::Try {
+                options: ExperimentOptions {
+                    mode: Some("build-only".to_string()),
+                    ..Default::default()
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_point_at_token() {
+        let err = parse_error("@crater-bot run stable beta mode=fast");
+        assert!(matches!(*err.kind, ParseErrorKind::InvalidValue { .. }));
+        assert_eq!((err.column, err.width), (29, 9));
+
+        let err = parse_error("@crater-bot run stable beta --color red");
+        assert!(matches!(*err.kind, ParseErrorKind::UnknownOption { ref key, .. } if key == "color"));
+        assert_eq!(err.column, 29);
+
+        let err = parse_error("@crater-bot run stable beta mode=clippy mode=fix");
+        assert_eq!(*err.kind, ParseErrorKind::DuplicateOption("mode".to_string()));
+        assert_eq!(err.column, 41);
+
+        let err = parse_error("@crater-bot status now");
+        assert_eq!(*err.kind, ParseErrorKind::UnexpectedArgument("now".to_string()));
+        assert_eq!(err.pointer(), "@crater-bot status now\n                   ^^^");
+
+        let err = parse_error("@crater-bot  frobnicate");
+        assert_eq!(
+            *err.kind,
+            ParseErrorKind::UnknownCommand {
+                name: "frobnicate".to_string(),
+                suggestion: None
+            }
+        );
+        assert_eq!(err.column, 14);
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let err = parse_error("@crater-bot stauts");
+        assert!(err.to_string().contains("Did you mean 'status'?"));
+
+        let err = parse_error("@crater-bot run stable beta --mdoe clippy");
+        assert!(matches!(
+            *err.kind,
+            ParseErrorKind::UnknownOption {
+                suggestion: Some("mode"),
+                ..
+            }
+        ));
+
+        let err = parse_error("@crater-bot try mode=chek-only");
+        assert!(err.to_string().contains("did you mean 'check-only'?"));
+
+        let err = parse_error("@crater-bot run stable beta crates=dmeo");
+        assert!(matches!(
+            *err.kind,
+            ParseErrorKind::InvalidValue {
+                suggestion: Some("demo"),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_parse_lang_option() {
+        let invocations = BotCommand::parse_invocations(
+            "@crater-bot status lang=en\n@crater-bot run stable --lang zh-cn\n@crater-bot list lang=fr",
+            "@crater-bot",
+        );
+        assert_eq!(invocations[0].command.as_ref().unwrap(), &BotCommand::Status);
+        assert_eq!(invocations[0].lang, Some(Locale::En));
+        // The error in a line that chose a language is reported in that language
+        assert!(invocations[1].command.is_err());
+        assert_eq!(invocations[1].lang, Some(Locale::ZhCn));
+        assert_eq!(invocations[2].lang, None);
+
+        let err = parse_error("@crater-bot list lang=fr");
+        assert!(err.to_string().contains("expected one of zh-CN, en"));
+        let err = parse_error("@crater-bot list --lagn en");
+        assert!(matches!(
+            *err.kind,
+            ParseErrorKind::UnknownOption {
+                suggestion: Some("lang"),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_parse_errors_localized() {
+        let err = parse_error("@crater-bot run stable beta mode=fast");
+        assert_eq!(
+            err.describe(Locale::En),
+            "Invalid command: invalid value 'fast' for option 'mode': expected one of \
+             build-and-test, build-only, check-only, clippy, rustdoc, unstable-features, fix (column 29)"
+        );
+        assert_eq!(
+            err.describe(Locale::ZhCn),
+            "无效指令：选项 'mode' 的值 'fast' 无效：应为 \
+             build-and-test, build-only, check-only, clippy, rustdoc, unstable-features, fix 之一（第 29 列）"
+        );
+    }
+
+    #[test]
+    fn test_parse_help_topic() {
+        let cmd = BotCommand::parse("@crater-bot help RUN pr", "@crater-bot").unwrap();
+        assert_eq!(cmd, Some(BotCommand::HelpTopic { command: "run pr" }));
+
+        let err = parse_error("@crater-bot help abrot");
+        assert!(err.to_string().contains("Did you mean 'abort'?"));
+        assert_eq!(err.column, 18);
+    }
+
+    #[test]
+    fn test_command_names_are_registered() {
+        for text in ["run a b", "run pr", "try", "status", "abort", "help", "help try", "list"] {
+            let cmd = BotCommand::parse(&format!("@crater-bot {}", text), "@crater-bot")
+                .unwrap()
+                .unwrap();
+            assert!(registry::find(cmd.name()).is_some(), "{}", cmd.name());
+        }
+    }
 }
@@ -0,0 +1,453 @@
+//! Tokenizer and argument grammar of bot command lines
+use crate::i18n::{self, Locale};
+use std::fmt;
+
+/// A word of a command line, with quotes removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub text: String,
+    /// 1-based column of the token's first character in the line
+    pub column: usize,
+    /// Width of the token as written, including quotes
+    pub width: usize,
+    /// Byte offset in `text` of the first `=` written outside quotes
+    unquoted_eq: Option<usize>,
+    /// Whether the token starts with a quote, so `"--mode"` is not a flag
+    quoted_start: bool,
+}
+
+/// An option given as `--key value`, `--key=value` or `key=value`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptionArg {
+    /// Lowercase key with `_` normalized to `-`
+    pub key: String,
+    pub value: String,
+    /// Position of the whole option, for error messages
+    pub column: usize,
+    pub width: usize,
+}
+
+/// Arguments following the command name
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Arguments {
+    pub positional: Vec<Token>,
+    pub options: Vec<OptionArg>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnterminatedQuote,
+    /// A `--flag` at the end of the line or followed by another flag
+    MissingValue(String),
+    UnknownCommand {
+        name: String,
+        suggestion: Option<&'static str>,
+    },
+    UnknownOption {
+        key: String,
+        suggestion: Option<&'static str>,
+    },
+    DuplicateOption(String),
+    InvalidValue {
+        option: String,
+        value: String,
+        expected: Expected,
+        suggestion: Option<&'static str>,
+    },
+    UnexpectedArgument(String),
+    /// `run` with fewer than two toolchains; holds the usage line
+    MissingToolchains(String),
+}
+
+/// What an option value should have been
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expected {
+    /// One of the listed values, e.g. `"full, demo"`
+    OneOf(String),
+}
+
+/// Longest command line `ParseError::pointer` shows in full, in characters
+pub const POINTER_WIDTH: usize = 100;
+
+/// A command line that does not follow the grammar, pointing at the offending token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Boxed to keep `Result`s carrying this error small
+    pub kind: Box<ParseErrorKind>,
+    /// The command line as written
+    pub line: String,
+    /// 1-based column of the offending token
+    pub column: usize,
+    pub width: usize,
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, line: &str, column: usize, width: usize) -> Self {
+        Self {
+            kind: Box::new(kind),
+            line: line.to_string(),
+            column,
+            width: width.max(1),
+        }
+    }
+
+    /// Error at `token`
+    pub fn at(kind: ParseErrorKind, line: &str, token: &Token) -> Self {
+        Self::new(kind, line, token.column, token.width)
+    }
+
+    /// The line with a caret marker under the offending token. Lines longer than
+    /// [`POINTER_WIDTH`] are cut to the part around the token.
+    pub fn pointer(&self) -> String {
+        let chars: Vec<char> = self.line.chars().collect();
+        let offset = self.column.saturating_sub(1);
+        if chars.len() <= POINTER_WIDTH {
+            return format!(
+                "{}\n{}{}",
+                self.line,
+                " ".repeat(offset),
+                "^".repeat(self.width)
+            );
+        }
+
+        // Keep a little context before the token
+        let start = offset
+            .saturating_sub(POINTER_WIDTH / 4)
+            .min(chars.len() - POINTER_WIDTH);
+        let end = start + POINTER_WIDTH;
+        let mut shown = String::new();
+        if start > 0 {
+            shown.push('…');
+        }
+        shown.extend(&chars[start..end]);
+        if end < chars.len() {
+            shown.push('…');
+        }
+        let indent = offset - start + usize::from(start > 0);
+        let width = self.width.min(end.saturating_sub(offset)).max(1);
+        format!("{}\n{}{}", shown, " ".repeat(indent), "^".repeat(width))
+    }
+}
+
+impl ParseError {
+    /// The error without its position, in `locale`
+    pub fn message(&self, locale: Locale) -> String {
+        let text = |key: &str| i18n::text(locale, key);
+        let suggestion = |key: &str, suggestion: &Option<&'static str>| {
+            suggestion
+                .map(|suggestion| i18n::format(locale, key, &[("suggestion", &suggestion)]))
+                .unwrap_or_default()
+        };
+
+        match self.kind.as_ref() {
+            ParseErrorKind::UnterminatedQuote => text("parse.unterminated_quote"),
+            ParseErrorKind::MissingValue(flag) => {
+                i18n::format(locale, "parse.missing_value", &[("flag", flag)])
+            }
+            ParseErrorKind::UnknownCommand {
+                name,
+                suggestion: suggested,
+            } => {
+                let mut message = i18n::format(locale, "parse.unknown_command", &[("name", name)]);
+                if suggested.is_some() {
+                    message.push(' ');
+                    message.push_str(&suggestion("parse.command_suggestion", suggested));
+                }
+                message.push(' ');
+                message.push_str(&text("parse.help_hint"));
+                message
+            }
+            ParseErrorKind::UnknownOption {
+                key,
+                suggestion: suggested,
+            } => {
+                i18n::format(locale, "parse.unknown_option", &[("key", key)])
+                    + &suggestion("parse.option_suggestion", suggested)
+            }
+            ParseErrorKind::DuplicateOption(key) => {
+                i18n::format(locale, "parse.duplicate_option", &[("key", key)])
+            }
+            ParseErrorKind::InvalidValue {
+                option,
+                value,
+                expected,
+                suggestion: suggested,
+            } => {
+                let expected = match expected {
+                    Expected::OneOf(values) => {
+                        i18n::format(locale, "parse.expected_one_of", &[("values", values)])
+                    }
+                };
+                i18n::format(
+                    locale,
+                    "parse.invalid_value",
+                    &[
+                        ("option", option),
+                        ("value", value),
+                        ("expected", &expected),
+                    ],
+                ) + &suggestion("parse.option_suggestion", suggested)
+            }
+            ParseErrorKind::UnexpectedArgument(argument) => i18n::format(
+                locale,
+                "parse.unexpected_argument",
+                &[("argument", argument)],
+            ),
+            ParseErrorKind::MissingToolchains(usage) => {
+                i18n::format(locale, "parse.missing_toolchains", &[("usage", usage)])
+            }
+        }
+    }
+
+    /// The error with its column, in `locale`
+    pub fn describe(&self, locale: Locale) -> String {
+        i18n::format(
+            locale,
+            "parse.error",
+            &[("message", &self.message(locale)), ("column", &self.column)],
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (column {})", self.message(Locale::En), self.column)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Split `line[start..]` into tokens. Double quotes allow `\"` and `\\` escapes;
+/// single quotes take their contents literally.
+pub fn tokenize(line: &str, start: usize) -> Result<Vec<Token>, ParseError> {
+    let column_of = |byte: usize| line[..byte].chars().count() + 1;
+
+    let mut tokens = Vec::new();
+    let mut current: Option<Token> = None;
+    // Open quote character and its byte offset
+    let mut quote: Option<(char, usize)> = None;
+    let mut chars = line[start..]
+        .char_indices()
+        .map(|(i, c)| (i + start, c))
+        .peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if quote.is_none() && c.is_whitespace() {
+            if let Some(mut token) = current.take() {
+                token.width = column_of(i) - token.column;
+                tokens.push(token);
+            }
+            continue;
+        }
+
+        let token = current.get_or_insert_with(|| Token {
+            text: String::new(),
+            column: column_of(i),
+            width: 0,
+            unquoted_eq: None,
+            quoted_start: c == '"' || c == '\'',
+        });
+
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some((c, i)),
+            (Some((open, _)), _) if c == open => quote = None,
+            (Some(('"', _)), '\\') => match chars.peek() {
+                Some(&(_, next @ ('"' | '\\'))) => {
+                    token.text.push(next);
+                    chars.next();
+                }
+                _ => token.text.push(c),
+            },
+            (None, '=') => {
+                token.unquoted_eq.get_or_insert(token.text.len());
+                token.text.push(c);
+            }
+            _ => token.text.push(c),
+        }
+    }
+
+    if let Some((_, i)) = quote {
+        return Err(ParseError::new(
+            ParseErrorKind::UnterminatedQuote,
+            line,
+            column_of(i),
+            1,
+        ));
+    }
+    if let Some(mut token) = current {
+        token.width = column_of(line.len()) - token.column;
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+impl Arguments {
+    /// Separate options from positional arguments
+    pub fn parse(line: &str, tokens: &[Token]) -> Result<Self, ParseError> {
+        let mut arguments = Arguments::default();
+        let mut tokens = tokens.iter().peekable();
+
+        while let Some(token) = tokens.next() {
+            let is_flag = !token.quoted_start && token.text.starts_with("--");
+            if is_flag {
+                let (key, value) = match token.unquoted_eq {
+                    Some(eq) => (&token.text[2..eq], Some(token.text[eq + 1..].to_string())),
+                    None => (&token.text[2..], None),
+                };
+                let (value, width) = match value {
+                    Some(value) => (value, token.width),
+                    None => match tokens
+                        .next_if(|next| next.quoted_start || !next.text.starts_with("--"))
+                    {
+                        Some(next) => (next.text.clone(), next.column + next.width - token.column),
+                        None => {
+                            return Err(ParseError::at(
+                                ParseErrorKind::MissingValue(key.to_string()),
+                                line,
+                                token,
+                            ))
+                        }
+                    },
+                };
+                arguments.options.push(OptionArg {
+                    key: normalize_key(key),
+                    value,
+                    column: token.column,
+                    width,
+                });
+                continue;
+            }
+
+            match token.unquoted_eq {
+                Some(eq) if is_option_key(&token.text[..eq]) => {
+                    arguments.options.push(OptionArg {
+                        key: normalize_key(&token.text[..eq]),
+                        value: token.text[eq + 1..].to_string(),
+                        column: token.column,
+                        width: token.width,
+                    });
+                }
+                // e.g. the toolchain `nightly+rustflags=-Zfoo`
+                _ => arguments.positional.push(token.clone()),
+            }
+        }
+
+        Ok(arguments)
+    }
+}
+
+fn normalize_key(key: &str) -> String {
+    key.to_lowercase().replace('_', "-")
+}
+
+/// `key` in `key=value`: a letter followed by letters, digits, `-` or `_`
+fn is_option_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(line: &str) -> Vec<String> {
+        tokenize(line, 0)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.text)
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize_quotes() {
+        assert_eq!(
+            texts(r#"run "nightly+rustflags=-C opt-level=3" 'it''s' "a \"b\" \\c""#),
+            vec![
+                "run",
+                "nightly+rustflags=-C opt-level=3",
+                "its",
+                r#"a "b" \c"#
+            ]
+        );
+        assert_eq!(texts(r#"crates="list:a, b""#), vec!["crates=list:a, b"]);
+    }
+
+    #[test]
+    fn test_token_columns() {
+        let tokens = tokenize("@bot run  \"a b\" c", 4).unwrap();
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|t| (t.column, t.width))
+                .collect::<Vec<_>>(),
+            vec![(6, 3), (11, 5), (17, 1)]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_quote() {
+        let err = tokenize("run stable \"beta", 0).unwrap_err();
+        assert_eq!(*err.kind, ParseErrorKind::UnterminatedQuote);
+        assert_eq!(err.column, 12);
+        assert_eq!(err.pointer(), "run stable \"beta\n           ^");
+    }
+
+    #[test]
+    fn test_pointer_of_long_line() {
+        let line = format!("run {} --colour red {}", "a".repeat(150), "b".repeat(150));
+        let err = ParseError::new(ParseErrorKind::UnterminatedQuote, &line, 156, 8);
+        let pointer = err.pointer();
+        let (shown, marker) = pointer.split_once('\n').unwrap();
+        assert_eq!(shown.chars().count(), POINTER_WIDTH + 2);
+        assert!(shown.starts_with('…') && shown.ends_with('…'));
+        // The marker still sits under the token
+        let token: String = shown
+            .chars()
+            .skip(marker.find('^').unwrap())
+            .take(8)
+            .collect();
+        assert_eq!(token, "--colour");
+    }
+
+    #[test]
+    fn test_arguments() {
+        let line =
+            "stable --mode check-only crates=\"list:a b\" --priority=5 x+rustflags=-Z \"--p\"";
+        let tokens = tokenize(line, 0).unwrap();
+        let arguments = Arguments::parse(line, &tokens).unwrap();
+
+        let positional: Vec<&str> = arguments
+            .positional
+            .iter()
+            .map(|t| t.text.as_str())
+            .collect();
+        assert_eq!(positional, vec!["stable", "x+rustflags=-Z", "--p"]);
+        let options: Vec<(&str, &str)> = arguments
+            .options
+            .iter()
+            .map(|o| (o.key.as_str(), o.value.as_str()))
+            .collect();
+        assert_eq!(
+            options,
+            vec![
+                ("mode", "check-only"),
+                ("crates", "list:a b"),
+                ("priority", "5")
+            ]
+        );
+        assert_eq!(
+            (arguments.options[0].column, arguments.options[0].width),
+            (8, 17)
+        );
+    }
+
+    #[test]
+    fn test_flag_without_value() {
+        let line = "stable beta --mode";
+        let tokens = tokenize(line, 0).unwrap();
+        let err = Arguments::parse(line, &tokens).unwrap_err();
+        assert_eq!(*err.kind, ParseErrorKind::MissingValue("mode".to_string()));
+        assert_eq!(err.column, 13);
+    }
+}
@@ -1,5 +1,8 @@
 pub mod commands;
+pub mod grammar;
 pub mod processor;
+pub mod ratelimit;
+pub mod registry;
 
-pub use commands::BotCommand;
+pub use commands::{BotCommand, ExperimentOptions};
 pub use processor::CommandProcessor;
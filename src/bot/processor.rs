@@ -1,182 +1,479 @@
-use crate::bot::commands::BotCommand;
+use crate::audit::{AuditEntry, Decision};
+use crate::bot::commands::{BotCommand, ExperimentOptions};
+use crate::bot::ratelimit::{LimitScope, Limited, RateLimiter};
+use crate::bot::registry::{self, CommandSpec};
 use crate::config::{BotConfig, Config};
-use crate::crater::{CraterClient, CreateExperimentRequest};
-use crate::error::Result;
-use crate::platforms::PlatformAdapter;
-use crate::utils::generate_experiment_name;
-use std::sync::Arc;
-use tracing::info;
+use crate::crater::{CraterClient, CreateExperimentRequest, ExperimentStatus};
+use crate::error::{BotError, Result};
+use crate::i18n::{self, Locale};
+use crate::platforms::{ExperimentMapping, PlatformAdapter, Thread};
+use crate::reload::Reloadable;
+use crate::request_id;
+use crate::templates::{RunStarted, StatusReport, Templates};
+use crate::utils::{generate_thread_experiment_name, parse_thread_experiment_name};
+use std::sync::{Arc, RwLock};
+use tracing::{info, Span};
+
+/// Where a command was given, by whom, and the language to answer in
+#[derive(Debug, Clone, Copy)]
+pub struct CommandContext<'a> {
+    pub project: &'a str,
+    pub thread: Thread,
+    pub author: &'a str,
+    pub locale: Locale,
+}
 
 pub struct CommandProcessor {
     crater_client: Arc<CraterClient>,
-    config: BotConfig,
-    callback_base_url: String,
+    config: RwLock<BotConfig>,
+    callback_base_url: RwLock<String>,
+    rate_limiter: RateLimiter,
+    templates: Arc<Templates>,
 }
 
 impl CommandProcessor {
-    pub fn new(crater_client: Arc<CraterClient>, config: &Config) -> Self {
+    pub fn new(
+        crater_client: Arc<CraterClient>,
+        config: &Config,
+        templates: Arc<Templates>,
+    ) -> Self {
         Self {
             crater_client,
-            config: config.bot.clone(),
-            callback_base_url: config.crater.callback_base_url.clone(),
+            config: RwLock::new(config.bot.clone()),
+            callback_base_url: RwLock::new(config.crater.callback_base_url.clone()),
+            rate_limiter: RateLimiter::new(config.rate_limit.clone()),
+            templates,
         }
     }
 
+    /// Snapshot of the current bot settings
+    fn bot_config(&self) -> BotConfig {
+        self.config.read().unwrap().clone()
+    }
+
     pub async fn process<P: PlatformAdapter>(
         &self,
         command: BotCommand,
         platform: &P,
-        project: &str,
-        issue_id: u64,
+        ctx: CommandContext<'_>,
+        audit: &mut AuditEntry,
     ) -> Result<String> {
+        let CommandContext {
+            project,
+            author,
+            locale,
+            ..
+        } = ctx;
+        if let Err(limited) = self.rate_limiter.check(author, project) {
+            info!(
+                "Rate limited {} on {}: {:?} limit, retry in {:?}",
+                author, project, limited.scope, limited.retry_after
+            );
+            audit.decision = Decision::RateLimited;
+            return Ok(rate_limited_message(&limited, locale));
+        }
+        audit.decision = Decision::Allowed;
+
         match command {
-            BotCommand::Run { toolchains } => {
-                self.handle_run(platform, project, issue_id, toolchains)
+            BotCommand::Run {
+                toolchains,
+                options,
+            } => {
+                self.handle_run(platform, ctx, toolchains, options, audit)
+                    .await
+            }
+            BotCommand::RunPr { options } => {
+                self.handle_run_pr(platform, ctx, options, audit)
                     .await
             }
-            BotCommand::Status => self.handle_status(platform, project, issue_id).await,
-            BotCommand::Abort => self.handle_abort(platform, project, issue_id).await,
-            BotCommand::Help => Ok(self.handle_help()),
-            BotCommand::List => self.handle_list().await,
+            BotCommand::Try { options } => {
+                self.handle_try(platform, ctx, options, audit)
+                    .await
+            }
+            BotCommand::Status => {
+                self.handle_status(platform, ctx, audit)
+                    .await
+            }
+            BotCommand::Abort => {
+                self.handle_abort(platform, ctx, audit)
+                    .await
+            }
+            BotCommand::Help => Ok(self.handle_help(locale)),
+            BotCommand::HelpTopic { command } => {
+                Ok(self.handle_help_topic(registry::command(command), locale))
+            }
+            BotCommand::List => self.handle_list(locale, audit).await,
         }
     }
 
     async fn handle_run<P: PlatformAdapter>(
         &self,
         platform: &P,
-        project: &str,
-        issue_id: u64,
+        ctx: CommandContext<'_>,
         toolchains: Vec<String>,
+        options: ExperimentOptions,
+        audit: &mut AuditEntry,
     ) -> Result<String> {
-        let experiment_name = generate_experiment_name(project, issue_id);
-        
+        let CommandContext {
+            project,
+            thread,
+            locale,
+            ..
+        } = ctx;
+        let config = self.bot_config();
+        if options.crates.as_deref() == Some("full") && !config.allow_full_crates {
+            return Err(BotError::InvalidCommand(i18n::text(
+                locale,
+                "error.full_crates_disabled",
+            )));
+        }
+        if let Some(max_running) = self.rate_limiter.max_running_per_project() {
+            audit.crater_call("list_experiments");
+            let running = self.count_running(project).await?;
+            if running >= max_running {
+                return Ok(i18n::format(
+                    locale,
+                    "run.capped",
+                    &[("project", &project), ("running", &running), ("max", &max_running)],
+                ));
+            }
+        }
+
+        let experiment_name = generate_thread_experiment_name(project, thread);
+        Span::current().record("experiment", experiment_name.as_str());
+
         info!("Creating experiment: {}", experiment_name);
 
-        let callback_url = format!("{}/callback/crater", self.callback_base_url);
+        let callback_url = format!(
+            "{}/callback/crater",
+            self.callback_base_url.read().unwrap()
+        );
 
         let request = CreateExperimentRequest {
             name: experiment_name.clone(),
             toolchains: toolchains.clone(),
-            mode: self.config.default_mode.clone(),
-            crate_select: self.config.default_crate_select.clone(),
+            mode: options.mode.unwrap_or(config.default_mode),
+            crate_select: options.crates.unwrap_or(config.default_crate_select),
             priority: 0,
             callback_url: Some(callback_url),
         };
 
+        audit.crater_call(format!("create_experiment {}", experiment_name));
         let experiment = self.crater_client.create_experiment(request).await?;
-        self.crater_client.run_experiment(&experiment.name).await?;
-
-        let message = format!(
-            "✅ 实验 `{}` 已创建并开始执行。\n\n\
-            工具链：{}\n\
-            模式：{}\n\
-            状态：{}\n\n\
-            我会在实验完成后通知您。",
-            experiment.name,
-            toolchains.join(" vs "),
-            experiment.mode,
-            experiment.status
-        );
 
-        // Store the experiment mapping for this issue
+        // Store the mapping before starting the experiment, so `status` and `abort`
+        // can still reach it if starting fails
         platform
-            .store_experiment_mapping(project, issue_id, &experiment_name)
+            .store_experiment_mapping(
+                project,
+                thread,
+                ExperimentMapping {
+                    experiment: experiment_name,
+                    request_id: request_id::current(),
+                },
+            )
             .await?;
 
+        audit.crater_call(format!("run_experiment {}", experiment.name));
+        self.crater_client.run_experiment(&experiment.name).await?;
+
+        let message = self.templates.render(
+            locale,
+            &RunStarted {
+                status: experiment.status.label(locale),
+                experiment: experiment.name,
+                toolchains,
+                mode: experiment.mode,
+                crates: experiment.crate_select,
+                priority: experiment.priority,
+                project: project.to_string(),
+                author: ctx.author.to_string(),
+            },
+        );
+
         Ok(message)
     }
 
+    /// `run pr`: compare the configured base toolchain against the merge request's build
+    async fn handle_run_pr<P: PlatformAdapter>(
+        &self,
+        platform: &P,
+        ctx: CommandContext<'_>,
+        options: ExperimentOptions,
+        audit: &mut AuditEntry,
+    ) -> Result<String> {
+        let Thread::MergeRequest(number) = ctx.thread else {
+            return Err(merge_request_only("run pr", ctx.locale));
+        };
+
+        let config = self.bot_config();
+        let toolchains = vec![
+            config.pr_base_toolchain,
+            config.pr_toolchain.replace("{number}", &number.to_string()),
+        ];
+        self.handle_run(platform, ctx, toolchains, options, audit)
+            .await
+    }
+
+    /// `try`: resolve the merge request's commits and compare `<base>#<base sha>`
+    /// against `try#<merge sha>`, falling back to the head commit without a merge commit
+    async fn handle_try<P: PlatformAdapter>(
+        &self,
+        platform: &P,
+        ctx: CommandContext<'_>,
+        options: ExperimentOptions,
+        audit: &mut AuditEntry,
+    ) -> Result<String> {
+        let CommandContext {
+            project,
+            thread,
+            locale,
+            ..
+        } = ctx;
+        let Thread::MergeRequest(number) = thread else {
+            return Err(merge_request_only("try", locale));
+        };
+
+        let merge_request = platform.get_merge_request(project, number).await?;
+        let try_sha = merge_request
+            .merge_commit_sha
+            .unwrap_or(merge_request.head_sha);
+        info!(
+            "Resolved {}!{}: base {}, try {}",
+            project, number, merge_request.base_sha, try_sha
+        );
+
+        let config = self.bot_config();
+        let toolchains = vec![
+            format!("{}#{}", config.pr_base_toolchain, merge_request.base_sha),
+            format!("try#{}", try_sha),
+        ];
+        self.handle_run(platform, ctx, toolchains, options, audit)
+            .await
+    }
+
+    /// Queued or running experiments created for issues and merge requests of `project`
+    async fn count_running(&self, project: &str) -> Result<usize> {
+        let experiments = self.crater_client.list_experiments().await?;
+        Ok(experiments
+            .iter()
+            .filter(|exp| {
+                matches!(
+                    exp.status,
+                    ExperimentStatus::Queued | ExperimentStatus::Running
+                )
+            })
+            .filter(|exp| {
+                parse_thread_experiment_name(&exp.name)
+                    .map(|(p, _)| p == project)
+                    .unwrap_or(false)
+            })
+            .count())
+    }
+
     async fn handle_status<P: PlatformAdapter>(
         &self,
         platform: &P,
-        project: &str,
-        issue_id: u64,
+        ctx: CommandContext<'_>,
+        audit: &mut AuditEntry,
     ) -> Result<String> {
-        let experiment_name = platform
-            .get_experiment_mapping(project, issue_id)
-            .await?;
+        let CommandContext {
+            project,
+            thread,
+            locale,
+            ..
+        } = ctx;
+        let mapping = platform.get_experiment_mapping(project, thread).await?;
 
-        if let Some(name) = experiment_name {
+        if let Some(ExperimentMapping { experiment: name, .. }) = mapping {
+            Span::current().record("experiment", name.as_str());
+            audit.crater_call(format!("get_experiment {}", name));
             let experiment = self.crater_client.get_experiment(&name).await?;
-            let message = format!(
-                "📊 实验状态\n\n\
-                名称：`{}`\n\
-                工具链：{}\n\
-                状态：{}\n\
-                模式：{}",
-                experiment.name,
-                experiment.toolchains.join(" vs "),
-                experiment.status,
-                experiment.mode
-            );
-            Ok(message)
+            Ok(self.templates.render(
+                locale,
+                &StatusReport {
+                    status: experiment.status.label(locale),
+                    experiment: experiment.name,
+                    toolchains: experiment.toolchains,
+                    mode: experiment.mode,
+                    project: project.to_string(),
+                },
+            ))
         } else {
-            Ok("当前没有与此 Issue 关联的实验。".to_string())
+            Ok(i18n::format(
+                locale,
+                "status.none",
+                &[("thread", &thread_label(thread, locale))],
+            ))
         }
     }
 
     async fn handle_abort<P: PlatformAdapter>(
         &self,
         platform: &P,
-        project: &str,
-        issue_id: u64,
+        ctx: CommandContext<'_>,
+        audit: &mut AuditEntry,
     ) -> Result<String> {
-        let experiment_name = platform
-            .get_experiment_mapping(project, issue_id)
-            .await?;
+        let CommandContext {
+            project,
+            thread,
+            locale,
+            ..
+        } = ctx;
+        let mapping = platform.get_experiment_mapping(project, thread).await?;
 
-        if let Some(name) = experiment_name {
+        if let Some(ExperimentMapping { experiment: name, .. }) = mapping {
+            Span::current().record("experiment", name.as_str());
+            audit.crater_call(format!("abort_experiment {}", name));
             self.crater_client.abort_experiment(&name).await?;
-            Ok(format!("⏹️ 实验 `{}` 已中止。", name))
+            Ok(i18n::format(locale, "abort.done", &[("name", &name)]))
         } else {
-            Ok("当前没有与此 Issue 关联的实验可以中止。".to_string())
+            Ok(i18n::format(
+                locale,
+                "abort.none",
+                &[("thread", &thread_label(thread, locale))],
+            ))
         }
     }
 
-    fn handle_help(&self) -> String {
+    fn handle_help(&self, locale: Locale) -> String {
+        let config = self.bot_config();
+        let prefix = &config.trigger_prefix;
+        let commands: String = registry::COMMANDS
+            .iter()
+            .map(|spec| format!("- `{}` - {}\n", spec.usage(prefix), spec.summary(locale)))
+            .collect();
         format!(
-            "## {} 帮助\n\n\
-            ### 可用命令\n\n\
-            - `{} run <toolchain1> <toolchain2>` - 创建并运行实验\n\
-            - `{} status` - 查看当前实验状态\n\
-            - `{} abort` - 中止当前实验\n\
-            - `{} list` - 列出所有实验\n\
-            - `{} help` - 显示此帮助信息\n\n\
-            ### 示例\n\n\
+            "{}\n\n{}\n\n{}\n{}\n\n\
             ```\n\
             {} run stable beta\n\
             {} run nightly-2024-01-01 stable\n\
-            ```",
-            self.config.name,
-            self.config.trigger_prefix,
-            self.config.trigger_prefix,
-            self.config.trigger_prefix,
-            self.config.trigger_prefix,
-            self.config.trigger_prefix,
-            self.config.trigger_prefix,
-            self.config.trigger_prefix
+            {} run \"nightly+rustflags=-C opt-level=3\" stable --mode check-only crates=\"list:serde, rand\"\n\
+            ```\n\n\
+            {}",
+            i18n::format(locale, "help.title", &[("name", &config.name)]),
+            i18n::text(locale, "help.commands"),
+            commands,
+            i18n::text(locale, "help.examples"),
+            prefix,
+            prefix,
+            prefix,
+            i18n::format(locale, "help.footer", &[("prefix", prefix)])
         )
     }
 
-    async fn handle_list(&self) -> Result<String> {
+    /// Usage, options with their configured defaults, permission and examples of one command
+    fn handle_help_topic(&self, spec: &CommandSpec, locale: Locale) -> String {
+        let config = self.bot_config();
+        let mut message = format!(
+            "{}\n\n{}\n\n{}\n",
+            i18n::format(locale, "help.topic_title", &[("command", &spec.name)]),
+            spec.description(locale, &config),
+            i18n::format(
+                locale,
+                "help.usage",
+                &[("usage", &spec.usage(&config.trigger_prefix))]
+            )
+        );
+
+        if !spec.options.is_empty() {
+            message.push_str(&format!("\n{}\n", i18n::text(locale, "help.options")));
+            for option in spec.options {
+                message.push_str(&i18n::format(
+                    locale,
+                    "help.option",
+                    &[
+                        ("key", &option.key),
+                        ("value", &option.value),
+                        ("description", &option.description(locale)),
+                        ("default", &(option.default)(&config)),
+                    ],
+                ));
+                message.push('\n');
+            }
+        }
+
+        message.push_str(&format!(
+            "\n{}\n\n{}\n```\n",
+            i18n::format(
+                locale,
+                "help.permission",
+                &[("permission", &spec.permission.label(locale))]
+            ),
+            i18n::text(locale, "help.example_heading")
+        ));
+        for example in spec.examples {
+            message.push_str(&format!("{} {}\n", config.trigger_prefix, example));
+        }
+        message.push_str("```");
+        message
+    }
+
+    async fn handle_list(&self, locale: Locale, audit: &mut AuditEntry) -> Result<String> {
+        audit.crater_call("list_experiments");
         let experiments = self.crater_client.list_experiments().await?;
         
         if experiments.is_empty() {
-            return Ok("当前没有实验。".to_string());
+            return Ok(i18n::text(locale, "list.empty"));
         }
 
-        let mut message = "## 实验列表\n\n".to_string();
+        let mut message = format!("{}\n\n", i18n::text(locale, "list.title"));
         for exp in experiments.iter().take(10) {
             message.push_str(&format!(
                 "- `{}` - {} ({})\n",
-                exp.name, exp.status, exp.toolchains.join(" vs ")
+                exp.name,
+                exp.status.label(locale),
+                exp.toolchains.join(" vs ")
             ));
         }
 
         if experiments.len() > 10 {
-            message.push_str(&format!("\n_...还有 {} 个实验_", experiments.len() - 10));
+            message.push('\n');
+            message.push_str(&i18n::format(
+                locale,
+                "list.more",
+                &[("count", &(experiments.len() - 10))],
+            ));
         }
 
         Ok(message)
     }
 }
+
+fn thread_label(thread: Thread, locale: Locale) -> String {
+    match thread {
+        Thread::Issue(_) => i18n::text(locale, "thread.issue"),
+        Thread::MergeRequest(_) => i18n::text(locale, "thread.merge_request"),
+    }
+}
+
+fn merge_request_only(command: &str, locale: Locale) -> BotError {
+    BotError::InvalidCommand(i18n::format(
+        locale,
+        "error.merge_request_only",
+        &[("command", &command)],
+    ))
+}
+
+fn rate_limited_message(limited: &Limited, locale: Locale) -> String {
+    let scope = match limited.scope {
+        LimitScope::User => i18n::text(locale, "rate_limit.user"),
+        LimitScope::Project => i18n::text(locale, "rate_limit.project"),
+        LimitScope::Global => i18n::text(locale, "rate_limit.global"),
+    };
+    // Round up so retrying at the stated time succeeds
+    let seconds = limited.retry_after.as_secs() + u64::from(limited.retry_after.subsec_nanos() > 0);
+    i18n::format(
+        locale,
+        "rate_limit.limited",
+        &[("scope", &scope), ("seconds", &seconds)],
+    )
+}
+
+impl Reloadable for CommandProcessor {
+    fn reload(&self, config: &Config) {
+        *self.config.write().unwrap() = config.bot.clone();
+        *self.callback_base_url.write().unwrap() = config.crater.callback_base_url.clone();
+        self.rate_limiter.update_config(config.rate_limit.clone());
+    }
+}
@@ -0,0 +1,289 @@
+//! Token-bucket rate limiting of bot commands
+use crate::config::{BucketConfig, RateLimitConfig};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Above this many tracked keys, buckets that have refilled completely are dropped
+const PRUNE_THRESHOLD: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn full(config: &BucketConfig, now: Instant) -> Self {
+        Self {
+            tokens: config.burst as f64,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, config: &BucketConfig, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        let rate = 1.0 / config.refill_secs.max(1) as f64;
+        self.tokens = (self.tokens + elapsed * rate).min(config.burst as f64);
+        self.updated = now;
+    }
+
+    /// Time until one token is available, or `None` if one is available now
+    fn wait_time(&self, config: &BucketConfig) -> Option<Duration> {
+        if self.tokens >= 1.0 {
+            None
+        } else {
+            let missing = 1.0 - self.tokens;
+            Some(Duration::from_secs_f64(
+                missing * config.refill_secs.max(1) as f64,
+            ))
+        }
+    }
+
+    fn is_full(&self, config: &BucketConfig) -> bool {
+        self.tokens >= config.burst as f64
+    }
+}
+
+/// Which limit rejected a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitScope {
+    User,
+    Project,
+    Global,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limited {
+    pub scope: LimitScope,
+    pub retry_after: Duration,
+}
+
+#[derive(Default)]
+struct Buckets {
+    users: HashMap<String, TokenBucket>,
+    projects: HashMap<String, TokenBucket>,
+    global: Option<TokenBucket>,
+}
+
+/// Per-author, per-project and global command limits
+pub struct RateLimiter {
+    config: Mutex<RateLimitConfig>,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config: Mutex::new(config),
+            buckets: Mutex::new(Buckets::default()),
+        }
+    }
+
+    /// Replace the limits. Only the buckets of a scope whose limit changed are reset,
+    /// so other reloads do not hand out fresh bursts.
+    pub fn update_config(&self, config: RateLimitConfig) {
+        let mut current = self.config.lock().unwrap();
+        let mut buckets = self.buckets.lock().unwrap();
+        if current.per_user != config.per_user {
+            buckets.users.clear();
+        }
+        if current.per_project != config.per_project {
+            buckets.projects.clear();
+        }
+        if current.global != config.global {
+            buckets.global = None;
+        }
+        *current = config;
+    }
+
+    pub fn max_running_per_project(&self) -> Option<usize> {
+        self.config.lock().unwrap().max_running_per_project
+    }
+
+    /// Take one token from every configured bucket, or none if any of them is empty
+    pub fn check(&self, author: &str, project: &str) -> Result<(), Limited> {
+        self.check_at(author, project, Instant::now())
+    }
+
+    fn check_at(&self, author: &str, project: &str, now: Instant) -> Result<(), Limited> {
+        let config = self.config.lock().unwrap().clone();
+        let mut buckets = self.buckets.lock().unwrap();
+        let buckets = &mut *buckets;
+
+        let mut selected: Vec<(LimitScope, &BucketConfig, &mut TokenBucket)> = Vec::new();
+        if let Some(limit) = &config.per_user {
+            prune(&mut buckets.users, limit, now);
+            let bucket = buckets
+                .users
+                .entry(author.to_string())
+                .or_insert_with(|| TokenBucket::full(limit, now));
+            selected.push((LimitScope::User, limit, bucket));
+        }
+        if let Some(limit) = &config.per_project {
+            prune(&mut buckets.projects, limit, now);
+            let bucket = buckets
+                .projects
+                .entry(project.to_string())
+                .or_insert_with(|| TokenBucket::full(limit, now));
+            selected.push((LimitScope::Project, limit, bucket));
+        }
+        if let Some(limit) = &config.global {
+            let bucket = buckets
+                .global
+                .get_or_insert_with(|| TokenBucket::full(limit, now));
+            selected.push((LimitScope::Global, limit, bucket));
+        }
+
+        for (_, limit, bucket) in selected.iter_mut() {
+            bucket.refill(limit, now);
+        }
+
+        // Report the longest wait so a retry at that time passes every bucket
+        let limited = selected
+            .iter()
+            .filter_map(|(scope, limit, bucket)| {
+                bucket.wait_time(limit).map(|retry_after| Limited {
+                    scope: *scope,
+                    retry_after,
+                })
+            })
+            .max_by_key(|limited| limited.retry_after);
+        if let Some(limited) = limited {
+            return Err(limited);
+        }
+
+        for (_, _, bucket) in selected.iter_mut() {
+            bucket.tokens -= 1.0;
+        }
+        Ok(())
+    }
+}
+
+fn prune(buckets: &mut HashMap<String, TokenBucket>, limit: &BucketConfig, now: Instant) {
+    if buckets.len() < PRUNE_THRESHOLD {
+        return;
+    }
+    buckets.retain(|_, bucket| {
+        let mut bucket = *bucket;
+        bucket.refill(limit, now);
+        !bucket.is_full(limit)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket(burst: u32, refill_secs: u64) -> Option<BucketConfig> {
+        Some(BucketConfig { burst, refill_secs })
+    }
+
+    #[test]
+    fn test_no_limits_configured() {
+        let limiter = RateLimiter::new(RateLimitConfig::default());
+        for _ in 0..100 {
+            assert!(limiter.check("alice", "org/repo").is_ok());
+        }
+    }
+
+    #[test]
+    fn test_per_user_limit() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            per_user: bucket(2, 60),
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        assert!(limiter.check_at("alice", "org/repo", now).is_ok());
+        assert!(limiter.check_at("alice", "org/repo", now).is_ok());
+        let limited = limiter.check_at("alice", "org/repo", now).unwrap_err();
+        assert_eq!(limited.scope, LimitScope::User);
+        assert_eq!(limited.retry_after, Duration::from_secs(60));
+
+        // Other users are unaffected
+        assert!(limiter.check_at("bob", "org/repo", now).is_ok());
+
+        // One token is back after the refill interval
+        let later = now + Duration::from_secs(60);
+        assert!(limiter.check_at("alice", "org/repo", later).is_ok());
+        assert!(limiter.check_at("alice", "org/repo", later).is_err());
+    }
+
+    #[test]
+    fn test_project_and_global_limits() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            per_project: bucket(1, 30),
+            global: bucket(2, 10),
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        assert!(limiter.check_at("alice", "org/a", now).is_ok());
+        let limited = limiter.check_at("bob", "org/a", now).unwrap_err();
+        assert_eq!(limited.scope, LimitScope::Project);
+
+        assert!(limiter.check_at("bob", "org/b", now).is_ok());
+        let limited = limiter.check_at("carol", "org/c", now).unwrap_err();
+        assert_eq!(limited.scope, LimitScope::Global);
+    }
+
+    #[test]
+    fn test_rejected_command_consumes_no_tokens() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            per_user: bucket(5, 60),
+            global: bucket(1, 60),
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        assert!(limiter.check_at("alice", "org/repo", now).is_ok());
+        assert!(limiter.check_at("alice", "org/repo", now).is_err());
+
+        let later = now + Duration::from_secs(60);
+        assert!(limiter.check_at("alice", "org/repo", later).is_ok());
+    }
+
+    #[test]
+    fn test_update_config_keeps_buckets_unless_changed() {
+        let config = RateLimitConfig {
+            per_user: bucket(1, 60),
+            per_project: bucket(1, 30),
+            global: bucket(2, 60),
+            max_running_per_project: Some(2),
+        };
+        let limiter = RateLimiter::new(config.clone());
+        let now = Instant::now();
+        assert!(limiter.check_at("alice", "org/repo", now).is_ok());
+
+        limiter.update_config(config.clone());
+        assert!(limiter.check_at("alice", "org/repo", now).is_err());
+
+        limiter.update_config(RateLimitConfig {
+            max_running_per_project: Some(5),
+            ..config.clone()
+        });
+        assert_eq!(limiter.max_running_per_project(), Some(5));
+        assert_eq!(
+            limiter
+                .check_at("alice", "org/repo", now)
+                .unwrap_err()
+                .scope,
+            LimitScope::User
+        );
+
+        // A new per-user limit resets only the per-user buckets
+        limiter.update_config(RateLimitConfig {
+            per_user: bucket(2, 60),
+            max_running_per_project: Some(5),
+            ..config
+        });
+        assert_eq!(
+            limiter
+                .check_at("alice", "org/repo", now)
+                .unwrap_err()
+                .scope,
+            LimitScope::Project
+        );
+    }
+}
@@ -0,0 +1,329 @@
+//! The bot's commands, their options and the values those accept. The parser, its
+//! error messages and `help` are all generated from these tables.
+
+use crate::config::BotConfig;
+use crate::i18n::{self, Locale};
+
+/// An option accepted as `key=value`, `--key value` or `--key=value`. Its
+/// description is the catalog entry `option.<key>.description`.
+#[derive(Debug, Clone, Copy)]
+pub struct OptionSpec {
+    pub key: &'static str,
+    /// Placeholder for the value in usage text
+    pub value: &'static str,
+    /// Value used when the option is omitted
+    pub default: fn(&BotConfig) -> String,
+}
+
+impl OptionSpec {
+    pub fn description(&self, locale: Locale) -> String {
+        i18n::text(locale, &format!("option.{}.description", self.key))
+    }
+}
+
+/// What a command may do. Commands are not restricted per user yet, so every
+/// level is open to anyone who can comment on the repository.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Only reads state
+    Read,
+    /// Creates or aborts experiments
+    Write,
+}
+
+impl Permission {
+    pub fn label(self, locale: Locale) -> String {
+        match self {
+            Permission::Read => i18n::text(locale, "permission.read"),
+            Permission::Write => i18n::text(locale, "permission.write"),
+        }
+    }
+}
+
+/// A command. Its texts are the catalog entries `command.<name>.summary` and
+/// `command.<name>.description`, with spaces in the name replaced by `-`.
+#[derive(Debug, Clone, Copy)]
+pub struct CommandSpec {
+    /// Words that invoke the command, e.g. `run pr`
+    pub name: &'static str,
+    /// Synopsis of the positional arguments
+    pub arguments: &'static str,
+    pub options: &'static [OptionSpec],
+    /// Example invocations without the trigger prefix
+    pub examples: &'static [&'static str],
+    pub permission: Permission,
+}
+
+impl CommandSpec {
+    /// Full usage line, e.g. `@crater-bot abort`
+    pub fn usage(&self, trigger_prefix: &str) -> String {
+        let mut usage = format!("{} {}", trigger_prefix, self.name);
+        if !self.arguments.is_empty() {
+            usage.push(' ');
+            usage.push_str(self.arguments);
+        }
+        for option in self.options {
+            usage.push_str(&format!(" [{}={}]", option.key, option.value));
+        }
+        usage
+    }
+
+    fn catalog_key(&self, field: &str) -> String {
+        format!("command.{}.{}", self.name.replace(' ', "-"), field)
+    }
+
+    /// One-line description shown by `help`
+    pub fn summary(&self, locale: Locale) -> String {
+        i18n::text(locale, &self.catalog_key("summary"))
+    }
+
+    /// Detailed description shown by `help <command>`, with configured values filled in
+    pub fn description(&self, locale: Locale, config: &BotConfig) -> String {
+        i18n::format(
+            locale,
+            &self.catalog_key("description"),
+            &[
+                ("pr_base_toolchain", &config.pr_base_toolchain),
+                ("pr_toolchain", &config.pr_toolchain),
+            ],
+        )
+    }
+}
+
+/// Options of commands that create an experiment
+pub const EXPERIMENT_OPTIONS: &[OptionSpec] = &[
+    OptionSpec {
+        key: "mode",
+        value: "<mode>",
+        default: |config| config.default_mode.clone(),
+    },
+    OptionSpec {
+        key: "crates",
+        value: "<crates>",
+        default: |config| config.default_crate_select.clone(),
+    },
+];
+
+/// Options accepted by every command
+pub const GLOBAL_OPTIONS: &[OptionSpec] = &[OptionSpec {
+    key: "lang",
+    value: "<lang>",
+    default: |config| config.language.code().to_string(),
+}];
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "run",
+        arguments: "<toolchain1> <toolchain2>",
+        options: EXPERIMENT_OPTIONS,
+        examples: &[
+            "run stable beta",
+            "run nightly-2024-01-01 stable mode=check-only",
+            "run \"nightly+rustflags=-C opt-level=3\" nightly crates=\"list:serde, rand\"",
+        ],
+        permission: Permission::Write,
+    },
+    CommandSpec {
+        name: "run pr",
+        arguments: "",
+        options: EXPERIMENT_OPTIONS,
+        examples: &["run pr", "run pr mode=check-only"],
+        permission: Permission::Write,
+    },
+    CommandSpec {
+        name: "try",
+        arguments: "",
+        options: EXPERIMENT_OPTIONS,
+        examples: &["try", "try crates=top-100"],
+        permission: Permission::Write,
+    },
+    CommandSpec {
+        name: "status",
+        arguments: "",
+        options: &[],
+        examples: &["status", "status lang=en"],
+        permission: Permission::Read,
+    },
+    CommandSpec {
+        name: "abort",
+        arguments: "",
+        options: &[],
+        examples: &["abort"],
+        permission: Permission::Write,
+    },
+    CommandSpec {
+        name: "list",
+        arguments: "",
+        options: &[],
+        examples: &["list"],
+        permission: Permission::Read,
+    },
+    CommandSpec {
+        name: "help",
+        arguments: "[<command>]",
+        options: &[],
+        examples: &["help", "help run", "help run pr"],
+        permission: Permission::Read,
+    },
+];
+
+/// Experiment modes supported by crater
+pub const MODES: &[&str] = &[
+    "build-and-test",
+    "build-only",
+    "check-only",
+    "clippy",
+    "rustdoc",
+    "unstable-features",
+    "fix",
+];
+
+/// Crate selections without a parameter; `top-<n>`, `random-<n>` and
+/// `list:<crates>` are accepted as well
+pub const CRATE_SELECTS: &[&str] = &["full", "demo", "dummy", "local"];
+
+/// The registered command called `name`
+pub fn command(name: &str) -> &'static CommandSpec {
+    find(name).expect("command is registered")
+}
+
+/// The command called `name`, ignoring case
+pub fn find(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS
+        .iter()
+        .find(|spec| spec.name.eq_ignore_ascii_case(name))
+}
+
+/// First words of all command names, for suggestions
+pub fn command_words() -> Vec<&'static str> {
+    let mut words: Vec<&'static str> = COMMANDS
+        .iter()
+        .filter_map(|spec| spec.name.split(' ').next())
+        .collect();
+    words.dedup();
+    words
+}
+
+pub fn is_valid_crate_select(value: &str) -> bool {
+    let counted = |prefix: &str| {
+        value
+            .strip_prefix(prefix)
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+    };
+    CRATE_SELECTS.contains(&value)
+        || counted("top-")
+        || counted("random-")
+        || value
+            .strip_prefix("list:")
+            .is_some_and(|list| !list.trim().is_empty())
+}
+
+/// The candidate closest to `input`, if it is close enough to be a likely typo
+pub fn suggest<'a>(input: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let input = input.to_lowercase();
+    // Allow one edit for short words and roughly one per three characters otherwise
+    let max_distance = (input.chars().count() / 3).max(1);
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(&input, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Edit distance between `a` and `b` counting insertions, deletions, substitutions
+/// and swaps of adjacent characters (optimal string alignment)
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // d[i][j]: distance between the first i characters of `a` and the first j of `b`
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("staus", "status"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("mdoe", "mode"), 1);
+    }
+
+    #[test]
+    fn test_suggest() {
+        assert_eq!(suggest("stauts", &command_words()), Some("status"));
+        assert_eq!(suggest("RUN", &command_words()), Some("run"));
+        assert_eq!(suggest("chek-only", MODES), Some("check-only"));
+        assert_eq!(suggest("frobnicate", &command_words()), None);
+    }
+
+    #[test]
+    fn test_crate_selects() {
+        for valid in ["demo", "top-100", "random-5", "list:serde, rand"] {
+            assert!(is_valid_crate_select(valid), "{}", valid);
+        }
+        for invalid in ["dem", "top-", "top-x", "list: "] {
+            assert!(!is_valid_crate_select(invalid), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_usage() {
+        assert_eq!(command("abort").usage("@crater-bot"), "@crater-bot abort");
+        assert_eq!(
+            command("run").usage("@bot"),
+            "@bot run <toolchain1> <toolchain2> [mode=<mode>] [crates=<crates>]"
+        );
+    }
+
+    #[test]
+    fn test_commands_documented() {
+        // The command list in COMMANDS.md mirrors the generated help
+        let docs = include_str!("../../docs/COMMANDS.md");
+        for spec in COMMANDS {
+            let line = format!(
+                "- `{}` - {}",
+                spec.usage("@crater-bot"),
+                spec.summary(Locale::ZhCn)
+            );
+            assert!(docs.contains(&line), "COMMANDS.md is missing: {}", line);
+        }
+    }
+
+    #[test]
+    fn test_texts_in_catalog() {
+        for &locale in Locale::ALL {
+            for spec in COMMANDS {
+                for field in ["summary", "description"] {
+                    let key = spec.catalog_key(field);
+                    assert_ne!(i18n::text(locale, &key), key);
+                }
+            }
+            for option in EXPERIMENT_OPTIONS.iter().chain(GLOBAL_OPTIONS) {
+                let key = format!("option.{}.description", option.key);
+                assert_ne!(option.description(locale), key);
+            }
+        }
+    }
+}
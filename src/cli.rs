@@ -0,0 +1,250 @@
+//! Command-line interface of the `crater-ohos-bot` binary
+use crate::audit::{AuditEntry, AuditLog, Decision};
+use crate::bot::BotCommand;
+use crate::config::Config;
+use crate::crater::CraterClient;
+use crate::error::{BotError, Result};
+use crate::http;
+use crate::platforms::Thread;
+use crate::server::{self, App};
+use crate::templates::Templates;
+use clap::{Parser, Subcommand};
+
+#[derive(Debug, Parser)]
+#[command(name = "crater-ohos-bot", version, about)]
+pub struct Cli {
+    /// Configuration file (defaults to $CRATER_OHOS_BOT_CONFIG, then `config`)
+    #[arg(long, short, global = true)]
+    pub config: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the bot server (default)
+    Serve {
+        /// Listen address, overriding `server.host` and `server.port`
+        #[arg(long)]
+        bind: Option<String>,
+    },
+    /// Load and validate the configuration, then exit
+    CheckConfig,
+    /// Dry-run the command parser against a comment, printing every command found
+    Parse {
+        /// Comment body to parse
+        comment: String,
+        /// Trigger prefix (defaults to `bot.trigger_prefix` from the config)
+        #[arg(long)]
+        prefix: Option<String>,
+    },
+    /// Send a recorded GitCode webhook payload to the bot
+    SimulateWebhook {
+        /// File containing the webhook JSON body
+        file: String,
+        /// Base URL of a running instance; an in-process instance is started if omitted
+        #[arg(long)]
+        url: Option<String>,
+        /// Value for the X-GitCode-Token header (defaults to the configured webhook secret)
+        #[arg(long)]
+        token: Option<String>,
+    },
+    /// Manage experiments on crater-ohos
+    Experiments {
+        #[command(subcommand)]
+        command: ExperimentsCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ExperimentsCommand {
+    /// List all experiments
+    List,
+    /// Abort a running experiment
+    Abort {
+        /// Experiment name
+        name: String,
+    },
+}
+
+impl Cli {
+    /// Configuration file named on the command line or by the environment
+    pub fn config_path(&self) -> String {
+        self.config.clone().unwrap_or_else(Config::path_from_env)
+    }
+
+    pub async fn run(self) -> Result<()> {
+        let config_path = self.config_path();
+
+        match self.command.unwrap_or(Command::Serve { bind: None }) {
+            Command::Serve { bind } => server::serve(&config_path, bind).await,
+            Command::CheckConfig => check_config(&config_path),
+            Command::Parse { comment, prefix } => parse(&config_path, &comment, prefix),
+            Command::SimulateWebhook { file, url, token } => {
+                simulate_webhook(&config_path, &file, url, token).await
+            }
+            Command::Experiments { command } => experiments(&config_path, command).await,
+        }
+    }
+}
+
+fn check_config(config_path: &str) -> Result<()> {
+    let config = Config::from_file(config_path)?;
+    Templates::new(&config.templates)?;
+    http::client(&config.http)?;
+    println!("{:#?}", config);
+    println!("Configuration OK: {}", config_path);
+    Ok(())
+}
+
+fn parse(config_path: &str, comment: &str, prefix: Option<String>) -> Result<()> {
+    let prefix = match prefix {
+        Some(prefix) => prefix,
+        None => Config::from_file(config_path)?.bot.trigger_prefix,
+    };
+
+    let commands = BotCommand::parse_all(comment, &prefix);
+    if commands.is_empty() {
+        println!("No command found (trigger prefix: {})", prefix);
+    }
+    for command in commands {
+        match command {
+            Ok(command) => println!("{:?}", command),
+            Err(BotError::Parse(e)) => println!("Error: {}\n{}", e, e.pointer()),
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+    Ok(())
+}
+
+async fn simulate_webhook(
+    config_path: &str,
+    file: &str,
+    url: Option<String>,
+    token: Option<String>,
+) -> Result<()> {
+    let payload = std::fs::read(file)?;
+
+    let config = match (&url, &token) {
+        (Some(_), Some(_)) => None,
+        _ => Some(Config::from_file(config_path)?),
+    };
+    let token = match token {
+        Some(token) => token,
+        None => config
+            .as_ref()
+            .and_then(|c| c.platforms.gitcode.as_ref())
+            .map(|gitcode| gitcode.webhook_secret.clone())
+            .ok_or_else(|| BotError::Config("GitCode platform not configured".to_string()))?,
+    };
+
+    let base_url = match url {
+        Some(url) => url,
+        None => {
+            let config = config.expect("config is loaded when no URL is given");
+            let app = App::build(&config, config_path)?;
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            tokio::spawn(async move { axum::serve(listener, app.router).await });
+            format!("http://{}", addr)
+        }
+    };
+
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/webhook/gitcode",
+            base_url.trim_end_matches('/')
+        ))
+        .header("Content-Type", "application/json")
+        .header("X-GitCode-Token", token)
+        .body(payload)
+        .send()
+        .await?;
+
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    println!("{}", status);
+    if !body.is_empty() {
+        println!("{}", body);
+    }
+    Ok(())
+}
+
+async fn experiments(config_path: &str, command: ExperimentsCommand) -> Result<()> {
+    let config = Config::from_file(config_path)?;
+    let client = CraterClient::new(config.crater, &config.http)?;
+    let audit_log = AuditLog::new(config.audit)?;
+
+    match command {
+        ExperimentsCommand::List => {
+            for experiment in client.list_experiments().await? {
+                println!(
+                    "{}\t{:?}\t{}",
+                    experiment.name,
+                    experiment.status,
+                    experiment.toolchains.join(" vs ")
+                );
+            }
+        }
+        ExperimentsCommand::Abort { name } => {
+            let operator = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+            let mut audit = AuditEntry::new("cli", "", Thread::Issue(0), &operator, "");
+            audit.command = Some(format!("experiments abort {}", name));
+            audit.decision = Decision::Allowed;
+            audit.crater_call(format!("abort_experiment {}", name));
+
+            let result = client.abort_experiment(&name).await;
+            audit.success = result.is_ok();
+            if let Err(e) = &result {
+                audit.error = Some(e.to_string());
+            }
+            audit_log.record(&audit);
+
+            result?;
+            println!("Aborted {}", name);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_command_is_serve() {
+        let cli = Cli::try_parse_from(["crater-ohos-bot"]).unwrap();
+        assert!(cli.command.is_none());
+    }
+
+    #[test]
+    fn test_serve_with_bind() {
+        let cli = Cli::try_parse_from([
+            "crater-ohos-bot",
+            "--config",
+            "bot.toml",
+            "serve",
+            "--bind",
+            "127.0.0.1:9000",
+        ])
+        .unwrap();
+        assert_eq!(cli.config.as_deref(), Some("bot.toml"));
+        assert!(matches!(
+            cli.command,
+            Some(Command::Serve { bind: Some(ref addr) }) if addr == "127.0.0.1:9000"
+        ));
+    }
+
+    #[test]
+    fn test_experiments_abort() {
+        let cli = Cli::try_parse_from(["crater-ohos-bot", "experiments", "abort", "user--repo-1"])
+            .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Experiments {
+                command: ExperimentsCommand::Abort { ref name }
+            }) if name == "user--repo-1"
+        ));
+    }
+}
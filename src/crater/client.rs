@@ -1,36 +1,56 @@
-use crate::config::CraterConfig;
+use crate::config::{Config, CraterConfig, HttpConfig};
 use crate::crater::types::{CreateExperimentRequest, Experiment, ExperimentList};
 use crate::error::{BotError, Result};
-use reqwest::Client;
-use tracing::{debug, error, info};
+use crate::http;
+use crate::reload::Reloadable;
+use crate::request_id;
+use crate::telemetry;
+use reqwest::{Client, Method, RequestBuilder};
+use std::sync::RwLock;
+use tracing::{debug, error, info, instrument};
 
 pub struct CraterClient {
     client: Client,
-    config: CraterConfig,
+    config: RwLock<CraterConfig>,
 }
 
 impl CraterClient {
-    pub fn new(config: CraterConfig) -> Result<Self> {
-        let client = Client::builder()
-            .build()
-            .map_err(|e| BotError::Internal(format!("Failed to create HTTP client: {}", e)))?;
+    pub fn new(config: CraterConfig, http: &HttpConfig) -> Result<Self> {
+        Ok(Self {
+            client: http::client(http)?,
+            config: RwLock::new(config),
+        })
+    }
 
-        Ok(Self { client, config })
+    fn api_url(&self) -> String {
+        self.config.read().unwrap().api_url.clone()
     }
 
-    fn auth_header(&self) -> String {
-        format!("Bearer {}", self.config.api_token)
+    /// A request to crater-ohos carrying the token, the current request ID and the
+    /// trace context
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        let builder = self
+            .client
+            .request(method, url)
+            .header(
+                "Authorization",
+                format!("Bearer {}", self.config.read().unwrap().api_token),
+            )
+            .headers(telemetry::trace_headers());
+        match request_id::current() {
+            Some(id) => builder.header(request_id::HEADER, id),
+            None => builder,
+        }
     }
 
+    #[instrument(name = "crater.create_experiment", skip_all, fields(experiment = %req.name))]
     pub async fn create_experiment(&self, req: CreateExperimentRequest) -> Result<Experiment> {
-        let url = format!("{}/api/v1/experiments", self.config.api_url);
+        let url = format!("{}/api/v1/experiments", self.api_url());
         info!("Creating experiment: {}", req.name);
         debug!("Request: {:?}", req);
 
         let response = self
-            .client
-            .post(&url)
-            .header("Authorization", self.auth_header())
+            .request(Method::POST, &url)
             .json(&req)
             .send()
             .await?;
@@ -50,14 +70,13 @@ impl CraterClient {
         Ok(experiment)
     }
 
+    #[instrument(name = "crater.list_experiments", skip_all)]
     pub async fn list_experiments(&self) -> Result<Vec<Experiment>> {
-        let url = format!("{}/api/v1/experiments", self.config.api_url);
+        let url = format!("{}/api/v1/experiments", self.api_url());
         info!("Listing experiments");
 
         let response = self
-            .client
-            .get(&url)
-            .header("Authorization", self.auth_header())
+            .request(Method::GET, &url)
             .send()
             .await?;
 
@@ -75,14 +94,13 @@ impl CraterClient {
         Ok(list.experiments)
     }
 
+    #[instrument(name = "crater.get_experiment", skip_all, fields(experiment = %name))]
     pub async fn get_experiment(&self, name: &str) -> Result<Experiment> {
-        let url = format!("{}/api/v1/experiments/{}", self.config.api_url, name);
+        let url = format!("{}/api/v1/experiments/{}", self.api_url(), name);
         info!("Getting experiment: {}", name);
 
         let response = self
-            .client
-            .get(&url)
-            .header("Authorization", self.auth_header())
+            .request(Method::GET, &url)
             .send()
             .await?;
 
@@ -100,14 +118,13 @@ impl CraterClient {
         Ok(experiment)
     }
 
+    #[instrument(name = "crater.run_experiment", skip_all, fields(experiment = %name))]
     pub async fn run_experiment(&self, name: &str) -> Result<()> {
-        let url = format!("{}/api/v1/experiments/{}/run", self.config.api_url, name);
+        let url = format!("{}/api/v1/experiments/{}/run", self.api_url(), name);
         info!("Running experiment: {}", name);
 
         let response = self
-            .client
-            .post(&url)
-            .header("Authorization", self.auth_header())
+            .request(Method::POST, &url)
             .send()
             .await?;
 
@@ -125,14 +142,13 @@ impl CraterClient {
         Ok(())
     }
 
+    #[instrument(name = "crater.abort_experiment", skip_all, fields(experiment = %name))]
     pub async fn abort_experiment(&self, name: &str) -> Result<()> {
-        let url = format!("{}/api/v1/experiments/{}/abort", self.config.api_url, name);
+        let url = format!("{}/api/v1/experiments/{}/abort", self.api_url(), name);
         info!("Aborting experiment: {}", name);
 
         let response = self
-            .client
-            .post(&url)
-            .header("Authorization", self.auth_header())
+            .request(Method::POST, &url)
             .send()
             .await?;
 
@@ -150,14 +166,13 @@ impl CraterClient {
         Ok(())
     }
 
+    #[instrument(name = "crater.delete_experiment", skip_all, fields(experiment = %name))]
     pub async fn delete_experiment(&self, name: &str) -> Result<()> {
-        let url = format!("{}/api/v1/experiments/{}", self.config.api_url, name);
+        let url = format!("{}/api/v1/experiments/{}", self.api_url(), name);
         info!("Deleting experiment: {}", name);
 
         let response = self
-            .client
-            .delete(&url)
-            .header("Authorization", self.auth_header())
+            .request(Method::DELETE, &url)
             .send()
             .await?;
 
@@ -175,3 +190,9 @@ impl CraterClient {
         Ok(())
     }
 }
+
+impl Reloadable for CraterClient {
+    fn reload(&self, config: &Config) {
+        *self.config.write().unwrap() = config.crater.clone();
+    }
+}
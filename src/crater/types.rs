@@ -1,3 +1,4 @@
+use crate::i18n::{self, Locale};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,16 +34,28 @@ pub enum ExperimentStatus {
     Aborted,
 }
 
-impl std::fmt::Display for ExperimentStatus {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl ExperimentStatus {
+    /// The status as crater reports it, e.g. `running`
+    pub fn as_str(&self) -> &'static str {
         match self {
-            ExperimentStatus::Queued => write!(f, "排队中"),
-            ExperimentStatus::Running => write!(f, "运行中"),
-            ExperimentStatus::Completed => write!(f, "已完成"),
-            ExperimentStatus::Failed => write!(f, "失败"),
-            ExperimentStatus::Aborted => write!(f, "已中止"),
+            ExperimentStatus::Queued => "queued",
+            ExperimentStatus::Running => "running",
+            ExperimentStatus::Completed => "completed",
+            ExperimentStatus::Failed => "failed",
+            ExperimentStatus::Aborted => "aborted",
         }
     }
+
+    /// The status for a comment in `locale`
+    pub fn label(&self, locale: Locale) -> String {
+        i18n::text(locale, &format!("experiment.{}", self.as_str()))
+    }
+}
+
+impl std::fmt::Display for ExperimentStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
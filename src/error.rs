@@ -1,5 +1,10 @@
+use crate::bot::grammar::ParseError;
+use crate::i18n::{self, Locale};
 use thiserror::Error;
 
+/// Longest error message posted in a comment, in characters
+const MAX_USER_MESSAGE_CHARS: usize = 300;
+
 #[derive(Error, Debug)]
 pub enum BotError {
     #[error("Configuration error: {0}")]
@@ -14,6 +19,9 @@ pub enum BotError {
     #[error("Invalid command: {0}")]
     InvalidCommand(String),
 
+    #[error("Invalid command: {0}")]
+    Parse(#[from] ParseError),
+
     #[error("Platform error: {0}")]
     Platform(String),
 
@@ -30,4 +38,70 @@ pub enum BotError {
     Internal(String),
 }
 
+impl BotError {
+    /// Whether the error only describes the user's own command, so its message can
+    /// be shown as is. Other errors may carry response bodies, URLs or error chains.
+    pub fn is_user_error(&self) -> bool {
+        matches!(self, BotError::Parse(_) | BotError::InvalidCommand(_))
+    }
+
+    /// Message safe to post in a public comment. Details of other than user errors
+    /// are only logged.
+    pub fn user_message(&self, locale: Locale) -> String {
+        let message = match self {
+            BotError::Parse(e) => e.describe(locale),
+            BotError::InvalidCommand(message) => message.clone(),
+            BotError::CraterApi(_) => i18n::text(locale, "error.crater"),
+            BotError::Http(_) => i18n::text(locale, "error.unavailable"),
+            BotError::Platform(_) => i18n::text(locale, "error.platform"),
+            BotError::Config(_)
+            | BotError::Json(_)
+            | BotError::WebhookVerification(_)
+            | BotError::Io(_)
+            | BotError::Internal(_) => i18n::text(locale, "error.internal"),
+        };
+        truncate(message, MAX_USER_MESSAGE_CHARS)
+    }
+}
+
+/// `text` cut to at most `max` characters, marking the cut with `…`
+fn truncate(text: String, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text;
+    }
+    let mut truncated: String = text.chars().take(max - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
 pub type Result<T> = std::result::Result<T, BotError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_message_hides_details() {
+        let err = BotError::CraterApi(
+            "Failed to create experiment: 500 - panicked at http://10.0.0.5/internal".to_string(),
+        );
+        assert!(!err.is_user_error());
+        assert_eq!(
+            err.user_message(Locale::En),
+            "The crater-ohos service could not handle the request"
+        );
+        assert!(!err.user_message(Locale::ZhCn).contains("10.0.0.5"));
+
+        let err = BotError::InvalidCommand("try can only be used on merge requests".to_string());
+        assert!(err.is_user_error());
+        assert_eq!(err.user_message(Locale::En), "try can only be used on merge requests");
+    }
+
+    #[test]
+    fn test_user_message_is_bounded() {
+        let err = BotError::InvalidCommand("x".repeat(1000));
+        let message = err.user_message(Locale::En);
+        assert_eq!(message.chars().count(), MAX_USER_MESSAGE_CHARS);
+        assert!(message.ends_with('…'));
+    }
+}
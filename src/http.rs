@@ -0,0 +1,82 @@
+//! Outbound HTTP clients, all built from the `[http]` settings
+use crate::config::HttpConfig;
+use crate::error::{BotError, Result};
+use reqwest::{Certificate, Client, Identity, NoProxy, Proxy};
+use std::time::Duration;
+
+/// A client with the configured timeouts, user agent, proxy and certificates
+pub fn client(config: &HttpConfig) -> Result<Client> {
+    let mut builder = Client::builder()
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .user_agent(&config.user_agent);
+
+    if let Some(url) = &config.proxy {
+        let proxy = Proxy::all(url)
+            .map_err(|e| BotError::Config(format!("Invalid http.proxy: {}", e)))?
+            .no_proxy(config.no_proxy.as_deref().and_then(NoProxy::from_string));
+        builder = builder.proxy(proxy);
+    }
+
+    if let Some(path) = &config.ca_bundle {
+        let certificates = Certificate::from_pem_bundle(&read(path)?)
+            .map_err(|e| BotError::Config(format!("Invalid CA bundle {}: {}", path, e)))?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    if let (Some(cert), Some(key)) = (&config.client_cert, &config.client_key) {
+        let identity = Identity::from_pkcs8_pem(&read(cert)?, &read(key)?)
+            .map_err(|e| BotError::Config(format!("Invalid client certificate {}: {}", cert, e)))?;
+        builder = builder.identity(identity);
+    }
+
+    builder
+        .build()
+        .map_err(|e| BotError::Internal(format!("Failed to create HTTP client: {}", e)))
+}
+
+fn read(path: &str) -> Result<Vec<u8>> {
+    std::fs::read(path).map_err(|e| BotError::Config(format!("Failed to read {}: {}", path, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_client() {
+        assert!(client(&HttpConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        let error = |config: HttpConfig| client(&config).unwrap_err().to_string();
+
+        let message = error(HttpConfig {
+            proxy: Some("not a url".to_string()),
+            ..Default::default()
+        });
+        assert!(message.contains("http.proxy"), "{}", message);
+
+        let message = error(HttpConfig {
+            ca_bundle: Some("/nonexistent/ca.pem".to_string()),
+            ..Default::default()
+        });
+        assert!(message.contains("/nonexistent/ca.pem"), "{}", message);
+
+        let path =
+            std::env::temp_dir().join(format!("crater-ohos-bot-{}-ca.pem", std::process::id()));
+        std::fs::write(
+            &path,
+            "-----BEGIN CERTIFICATE-----\nnot base64\n-----END CERTIFICATE-----\n",
+        )
+        .unwrap();
+        let message = error(HttpConfig {
+            ca_bundle: Some(path.to_string_lossy().into_owned()),
+            ..Default::default()
+        });
+        assert!(message.contains("Invalid CA bundle"), "{}", message);
+    }
+}
@@ -0,0 +1,300 @@
+//! Message catalog of user-facing text, looked up by key with `{name}` placeholders
+//! filled in from arguments. Comments about experiments and errors are rendered from
+//! templates instead, see [`crate::templates`].
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Locale {
+    #[default]
+    #[serde(rename = "zh-CN", alias = "zh", alias = "zh-cn")]
+    ZhCn,
+    #[serde(rename = "en", alias = "en-US", alias = "en-us")]
+    En,
+}
+
+impl Locale {
+    pub const ALL: &'static [Locale] = &[Locale::ZhCn, Locale::En];
+
+    /// Language tag used in config and the `lang` option
+    pub fn code(self) -> &'static str {
+        match self {
+            Locale::ZhCn => "zh-CN",
+            Locale::En => "en",
+        }
+    }
+
+    /// Parse a language tag, ignoring case and accepting `_` for `-`
+    pub fn parse(tag: &str) -> Option<Self> {
+        match tag.to_lowercase().replace('_', "-").as_str() {
+            "zh-cn" | "zh" => Some(Locale::ZhCn),
+            "en" | "en-us" => Some(Locale::En),
+            _ => None,
+        }
+    }
+
+    fn catalog(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Locale::ZhCn => ZH_CN,
+            Locale::En => EN,
+        }
+    }
+}
+
+/// The text for `key`, falling back to the default locale and then the key itself
+pub fn text(locale: Locale, key: &str) -> String {
+    lookup(locale, key)
+        .or_else(|| lookup(Locale::default(), key))
+        .unwrap_or(key)
+        .to_string()
+}
+
+/// The text for `key` with each `{name}` placeholder replaced by its argument
+pub fn format(locale: Locale, key: &str, args: &[(&str, &dyn Display)]) -> String {
+    fill(&text(locale, key), args)
+}
+
+fn lookup(locale: Locale, key: &str) -> Option<&'static str> {
+    locale
+        .catalog()
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, text)| *text)
+}
+
+/// Replace `{name}` placeholders in one pass, so argument values are never expanded
+fn fill(template: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        result.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let value = after.find('}').and_then(|close| {
+            args.iter()
+                .find(|(name, _)| *name == &after[..close])
+                .map(|(_, value)| (close, value.to_string()))
+        });
+        match value {
+            Some((close, value)) => {
+                result.push_str(&value);
+                rest = &after[close + 1..];
+            }
+            None => {
+                result.push('{');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+const ZH_CN: &[(&str, &str)] = &[
+    // Replies to commands
+    ("error.merge_request_only", "{command} 只能在合并请求中使用"),
+    ("error.full_crates_disabled", "未启用 crates=full，请选择 top-<n>、random-<n> 或 list:<crates> 等较小的范围"),
+    ("error.crater", "crater-ohos 服务处理请求失败"),
+    ("error.unavailable", "暂时无法连接到所需的服务，请稍后重试"),
+    ("error.platform", "代码托管平台请求失败"),
+    ("error.internal", "Bot 内部错误"),
+    ("reply.too_many_commands", "⚠️ 每条评论最多执行 {max} 条指令，其余 {skipped} 条已忽略。"),
+    ("rate_limit.user", "用户"),
+    ("rate_limit.project", "仓库"),
+    ("rate_limit.global", "全局"),
+    ("rate_limit.limited", "⏳ 指令过于频繁（{scope}限制），请在 {seconds} 秒后重试。"),
+    ("run.capped", "⏳ 仓库 `{project}` 已有 {running} 个排队或运行中的实验（上限 {max}），请等待现有实验完成后重试。"),
+    ("status.none", "当前没有与此{thread}关联的实验。"),
+    ("abort.done", "⏹️ 实验 `{name}` 已中止。"),
+    ("abort.none", "当前没有与此{thread}关联的实验可以中止。"),
+    ("list.empty", "当前没有实验。"),
+    ("list.title", "## 实验列表"),
+    ("list.more", "_...还有 {count} 个实验_"),
+    ("thread.issue", " Issue "),
+    ("thread.merge_request", "合并请求"),
+    // Experiment status labels
+    ("experiment.queued", "排队中"),
+    ("experiment.running", "运行中"),
+    ("experiment.completed", "已完成"),
+    ("experiment.failed", "失败"),
+    ("experiment.aborted", "已中止"),
+    // Notifications from crater callbacks
+    ("callback.aborted", "⏹️ 实验 `{name}` 已中止。"),
+    ("callback.status", "📊 实验 `{name}` 状态更新：{status}"),
+    // Help
+    ("help.title", "## {name} 帮助"),
+    ("help.commands", "### 可用命令"),
+    ("help.examples", "### 示例"),
+    ("help.footer", "包含空格的参数需要用引号包围。使用 `{prefix} help <command>` 查看命令的详细说明，在任意指令后加 `lang=en` 可以切换回复语言。"),
+    ("help.topic_title", "## `{command}` 帮助"),
+    ("help.usage", "**用法**：`{usage}`"),
+    ("help.options", "**选项**："),
+    ("help.option", "- `{key}={value}` - {description}（默认：`{default}`）"),
+    ("help.permission", "**权限**：{permission}，任何可以评论的用户均可使用"),
+    ("help.example_heading", "**示例**："),
+    ("permission.read", "只读"),
+    ("permission.write", "会创建或中止实验"),
+    ("command.run.summary", "创建并运行实验"),
+    ("command.run.description", "创建一个对比两个或更多工具链的 crater 实验并立即开始执行。工具链可以是 `stable`、`beta`、`nightly`、`nightly-YYYY-MM-DD` 或版本号，包含空格的工具链（例如带 rustflags）需要用引号包围。每个 Issue 或合并请求同时只能有一个实验。"),
+    ("command.run-pr.summary", "在合并请求中对比基线工具链与该合并请求的构建"),
+    ("command.run-pr.description", "只能在合并请求中使用。对比基线工具链 `{pr_base_toolchain}` 与该合并请求的构建产物 `{pr_toolchain}`（`{number}` 为合并请求编号）。"),
+    ("command.try.summary", "在合并请求中对比基线提交与合并提交（`try#<sha>`）"),
+    ("command.try.description", "只能在合并请求中使用。通过平台 API 查询合并请求的提交，对比 `{pr_base_toolchain}#<基准提交>` 与 `try#<合并提交>`；平台尚未生成合并提交时使用源分支的最新提交。"),
+    ("command.status.summary", "查看当前实验状态"),
+    ("command.status.description", "显示与当前 Issue 或合并请求关联的实验的名称、工具链、状态和模式。"),
+    ("command.abort.summary", "中止当前实验"),
+    ("command.abort.description", "中止与当前 Issue 或合并请求关联的实验。已中止的实验无法恢复，需要重新运行。"),
+    ("command.list.summary", "列出所有实验"),
+    ("command.list.description", "列出 crater-ohos 上的实验（最多 10 个）及其状态和工具链。"),
+    ("command.help.summary", "显示帮助信息，或某个命令的详细说明"),
+    ("command.help.description", "不带参数时列出所有命令；指定命令（例如 `run` 或 `run pr`）时显示该命令的用法、选项、默认值和示例。"),
+    ("option.mode.description", "实验模式：build-and-test、build-only、check-only、clippy、rustdoc、unstable-features 或 fix"),
+    ("option.crates.description", "参与实验的包：full、demo、dummy、local、top-<n>、random-<n> 或 list:<包列表>"),
+    ("option.lang.description", "回复语言：zh-CN 或 en，适用于所有指令"),
+    // Parse errors
+    ("parse.error", "无效指令：{message}（第 {column} 列）"),
+    ("parse.unterminated_quote", "引号未闭合"),
+    ("parse.missing_value", "选项 --{flag} 缺少值"),
+    ("parse.unknown_command", "未知命令：{name}。"),
+    ("parse.command_suggestion", "您是否想输入 '{suggestion}'？"),
+    ("parse.help_hint", "使用 'help' 查看可用命令"),
+    ("parse.unknown_option", "未知选项 '{key}'"),
+    ("parse.option_suggestion", "，您是否想输入 '{suggestion}'？"),
+    ("parse.duplicate_option", "选项 '{key}' 重复出现"),
+    ("parse.invalid_value", "选项 '{option}' 的值 '{value}' 无效：应为{expected}"),
+    ("parse.expected_one_of", " {values} 之一"),
+    ("parse.unexpected_argument", "多余的参数 '{argument}'"),
+    ("parse.missing_toolchains", "run 命令需要至少两个工具链参数。用法：{usage}"),
+];
+
+const EN: &[(&str, &str)] = &[
+    // Replies to commands
+    ("error.merge_request_only", "{command} can only be used on merge requests"),
+    ("error.full_crates_disabled", "crates=full is not enabled; choose a smaller selection such as top-<n>, random-<n> or list:<crates>"),
+    ("error.crater", "The crater-ohos service could not handle the request"),
+    ("error.unavailable", "A required service is unreachable, please retry later"),
+    ("error.platform", "The request to the code hosting platform failed"),
+    ("error.internal", "Internal bot error"),
+    ("reply.too_many_commands", "⚠️ At most {max} commands are run per comment; the remaining {skipped} were ignored."),
+    ("rate_limit.user", "user"),
+    ("rate_limit.project", "repository"),
+    ("rate_limit.global", "global"),
+    ("rate_limit.limited", "⏳ Too many commands ({scope} limit), please retry in {seconds} seconds."),
+    ("run.capped", "⏳ Repository `{project}` already has {running} queued or running experiments (limit {max}); please retry once one of them has finished."),
+    ("status.none", "There is no experiment associated with this {thread}."),
+    ("abort.done", "⏹️ Experiment `{name}` has been aborted."),
+    ("abort.none", "There is no experiment associated with this {thread} to abort."),
+    ("list.empty", "There are no experiments."),
+    ("list.title", "## Experiments"),
+    ("list.more", "_...and {count} more_"),
+    ("thread.issue", "issue"),
+    ("thread.merge_request", "merge request"),
+    // Experiment status labels
+    ("experiment.queued", "queued"),
+    ("experiment.running", "running"),
+    ("experiment.completed", "completed"),
+    ("experiment.failed", "failed"),
+    ("experiment.aborted", "aborted"),
+    // Notifications from crater callbacks
+    ("callback.aborted", "⏹️ Experiment `{name}` was aborted."),
+    ("callback.status", "📊 Experiment `{name}` status update: {status}"),
+    // Help
+    ("help.title", "## {name} help"),
+    ("help.commands", "### Commands"),
+    ("help.examples", "### Examples"),
+    ("help.footer", "Quote arguments that contain spaces. Use `{prefix} help <command>` for details on a command, and append `lang=zh-CN` to any command to change the reply language."),
+    ("help.topic_title", "## `{command}` help"),
+    ("help.usage", "**Usage**: `{usage}`"),
+    ("help.options", "**Options**:"),
+    ("help.option", "- `{key}={value}` - {description} (default: `{default}`)"),
+    ("help.permission", "**Permission**: {permission}; available to anyone who can comment"),
+    ("help.example_heading", "**Examples**:"),
+    ("permission.read", "read-only"),
+    ("permission.write", "creates or aborts experiments"),
+    ("command.run.summary", "Create and start an experiment"),
+    ("command.run.description", "Creates a crater experiment comparing two or more toolchains and starts it right away. Toolchains can be `stable`, `beta`, `nightly`, `nightly-YYYY-MM-DD` or a version number; quote toolchains that contain spaces (e.g. with rustflags). Each issue or merge request can have one experiment at a time."),
+    ("command.run-pr.summary", "On a merge request, compare the base toolchain with the merge request's build"),
+    ("command.run-pr.description", "Only available on merge requests. Compares the base toolchain `{pr_base_toolchain}` with the merge request's build `{pr_toolchain}` (`{number}` is the merge request number)."),
+    ("command.try.summary", "On a merge request, compare the base commit with the merge commit (`try#<sha>`)"),
+    ("command.try.description", "Only available on merge requests. Looks up the merge request's commits through the platform API and compares `{pr_base_toolchain}#<base commit>` with `try#<merge commit>`, using the source branch's latest commit until the platform has created a merge commit."),
+    ("command.status.summary", "Show the current experiment's status"),
+    ("command.status.description", "Shows the name, toolchains, status and mode of the experiment associated with this issue or merge request."),
+    ("command.abort.summary", "Abort the current experiment"),
+    ("command.abort.description", "Aborts the experiment associated with this issue or merge request. Aborted experiments cannot be resumed and have to be run again."),
+    ("command.list.summary", "List all experiments"),
+    ("command.list.description", "Lists the experiments on crater-ohos (at most 10) with their status and toolchains."),
+    ("command.help.summary", "Show this help, or details on one command"),
+    ("command.help.description", "Without arguments, lists all commands; with a command (e.g. `run` or `run pr`), shows its usage, options, defaults and examples."),
+    ("option.mode.description", "Experiment mode: build-and-test, build-only, check-only, clippy, rustdoc, unstable-features or fix"),
+    ("option.crates.description", "Crates to test: full, demo, dummy, local, top-<n>, random-<n> or list:<crates>"),
+    ("option.lang.description", "Reply language, zh-CN or en; accepted by every command"),
+    // Parse errors
+    ("parse.error", "Invalid command: {message} (column {column})"),
+    ("parse.unterminated_quote", "unterminated quote"),
+    ("parse.missing_value", "option --{flag} requires a value"),
+    ("parse.unknown_command", "Unknown command: {name}."),
+    ("parse.command_suggestion", "Did you mean '{suggestion}'?"),
+    ("parse.help_hint", "Use 'help' to see available commands"),
+    ("parse.unknown_option", "unknown option '{key}'"),
+    ("parse.option_suggestion", ", did you mean '{suggestion}'?"),
+    ("parse.duplicate_option", "option '{key}' is given more than once"),
+    ("parse.invalid_value", "invalid value '{value}' for option '{option}': expected {expected}"),
+    ("parse.expected_one_of", "one of {values}"),
+    ("parse.unexpected_argument", "unexpected argument '{argument}'"),
+    ("parse.missing_toolchains", "run command requires at least two toolchain arguments. Usage: {usage}"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placeholders(text: &str) -> Vec<&str> {
+        let mut names: Vec<&str> = text
+            .split('{')
+            .skip(1)
+            .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    #[test]
+    fn test_catalogs_are_complete() {
+        for &(key, zh) in ZH_CN {
+            let en = lookup(Locale::En, key).unwrap_or_else(|| panic!("en is missing {}", key));
+            assert_eq!(
+                placeholders(zh),
+                placeholders(en),
+                "placeholders of {}",
+                key
+            );
+        }
+        for &(key, _) in EN {
+            assert!(
+                lookup(Locale::ZhCn, key).is_some(),
+                "zh-CN is missing {}",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            format(Locale::En, "abort.done", &[("name", &"org--repo-1")]),
+            "⏹️ Experiment `org--repo-1` has been aborted."
+        );
+        // Values are inserted verbatim, even if they look like placeholders
+        assert_eq!(
+            fill("{a} {b} {c}", &[("a", &"{b}"), ("b", &1)]),
+            "{b} 1 {c}"
+        );
+        assert_eq!(text(Locale::En, "no.such.key"), "no.such.key");
+    }
+
+    #[test]
+    fn test_parse_locale() {
+        assert_eq!(Locale::parse("EN"), Some(Locale::En));
+        assert_eq!(Locale::parse("zh_CN"), Some(Locale::ZhCn));
+        assert_eq!(Locale::parse("fr"), None);
+    }
+}
@@ -1,9 +1,21 @@
 pub mod api;
+pub mod audit;
 pub mod bot;
+pub mod cli;
 pub mod config;
 pub mod crater;
 pub mod error;
+pub mod http;
+pub mod i18n;
+pub mod logging;
+pub mod metrics;
 pub mod platforms;
+pub mod reload;
+pub mod request_id;
+pub mod server;
+pub mod telemetry;
+pub mod templates;
+pub mod tls;
 pub mod utils;
 pub mod webhook;
 
@@ -0,0 +1,276 @@
+//! Log output: human-readable lines or JSON objects, to stderr or rotated files,
+//! and optionally trace export (see [`crate::telemetry`]).
+//!
+//! Spans carry the fields that identify the work being done, so every event below
+//! them can be found by these names:
+//!
+//! | Field        | Set by                                      |
+//! |--------------|---------------------------------------------|
+//! | `request_id` | every HTTP request                          |
+//! | `platform`   | webhooks and crater-ohos callbacks          |
+//! | `project`    | webhooks and crater-ohos callbacks          |
+//! | `issue`      | webhooks and callbacks, `#12` or `!3`       |
+//! | `command`    | each command of a comment, e.g. `run pr`    |
+//! | `experiment` | commands and callbacks about an experiment  |
+//! | `latency`    | the end of each HTTP request, milliseconds  |
+use crate::config::{LogFileConfig, LogFormat, LogRotation, LoggingConfig};
+use crate::error::{BotError, Result};
+use crate::telemetry;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use serde_json::{Map, Value};
+use std::fmt;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::format::{JsonFields, Writer};
+use tracing_subscriber::fmt::time::{FormatTime, SystemTime};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormattedFields, MakeWriter};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+/// Filter used when `RUST_LOG` is not set
+const DEFAULT_FILTER: &str = "crater_ohos_bot=debug,tower_http=debug";
+
+/// Flushes buffered log lines and exports pending spans when dropped
+pub struct LogGuard {
+    _file: Option<WorkerGuard>,
+    tracer_provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for LogGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.tracer_provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to export pending spans: {}", e);
+            }
+        }
+    }
+}
+
+/// Install the global subscriber. File output and trace export run on background
+/// threads; keep the returned guard until exit. Call this outside the async runtime,
+/// see [`telemetry::tracer_provider`].
+pub fn init(config: &LoggingConfig) -> Result<LogGuard> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| DEFAULT_FILTER.into());
+
+    let (writer, file_guard) = match &config.file {
+        Some(file) => {
+            let (writer, guard) = tracing_appender::non_blocking(file_appender(file)?);
+            (BoxMakeWriter::new(writer), Some(guard))
+        }
+        None => (BoxMakeWriter::new(std::io::stderr), None),
+    };
+    let tracer_provider = config
+        .otlp
+        .as_ref()
+        .map(telemetry::tracer_provider)
+        .transpose()?;
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(layer(config.format, writer, config.file.is_none()))
+        .with(tracer_provider.as_ref().map(telemetry::layer))
+        .try_init()
+        .map_err(|e| BotError::Config(format!("Cannot set up logging: {}", e)))?;
+    Ok(LogGuard {
+        _file: file_guard,
+        tracer_provider,
+    })
+}
+
+/// Formatting layer writing to `writer`; `ansi` colours text output
+pub fn layer<S, W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    match format {
+        LogFormat::Text => tracing_subscriber::fmt::layer()
+            .with_writer(writer)
+            .with_ansi(ansi)
+            .boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .fmt_fields(JsonFields::new())
+            .event_format(FlatJson)
+            .with_writer(writer)
+            .boxed(),
+    }
+}
+
+fn file_appender(config: &LogFileConfig) -> Result<RollingFileAppender> {
+    let rotation = match config.rotation {
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Never => Rotation::NEVER,
+    };
+    let mut builder = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(&config.prefix);
+    if let Some(max_files) = config.max_files {
+        builder = builder.max_log_files(max_files);
+    }
+    builder
+        .build(&config.dir)
+        .map_err(|e| BotError::Config(format!("Cannot open log directory {}: {}", config.dir, e)))
+}
+
+/// One JSON object per event with `timestamp`, `level`, `target`, the fields of
+/// every enclosing span and the event's own fields (including `message`) at the top
+/// level, so aggregators see the same field names whatever span set them. Inner
+/// spans override outer ones and the event overrides both.
+pub struct FlatJson;
+
+impl<S> FormatEvent<S, JsonFields> for FlatJson
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, JsonFields>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut object = Map::new();
+
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+        object.insert("timestamp".to_string(), Value::String(timestamp));
+        let metadata = event.metadata();
+        object.insert("level".to_string(), metadata.level().as_str().into());
+        object.insert("target".to_string(), metadata.target().into());
+
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                let extensions = span.extensions();
+                let Some(fields) = extensions.get::<FormattedFields<JsonFields>>() else {
+                    continue;
+                };
+                // Spans without fields are formatted as an empty string
+                if let Ok(Value::Object(fields)) = serde_json::from_str(fields) {
+                    object.extend(fields);
+                }
+            }
+        }
+
+        event.record(&mut JsonVisitor(&mut object));
+        writeln!(writer, "{}", Value::Object(object))
+    }
+}
+
+/// Collects event fields, keeping numbers and booleans as JSON values
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use tracing::{field, info, info_span};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn capture(format: LogFormat, f: impl FnOnce()) -> String {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber =
+            tracing_subscriber::registry().with(layer(format, move || writer.clone(), false));
+        tracing::subscriber::with_default(subscriber, f);
+        let output = buffer.0.lock().unwrap().clone();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_json_flattens_span_fields() {
+        let output = capture(LogFormat::Json, || {
+            let request = info_span!("request", request_id = "req-1");
+            let _request = request.enter();
+            let webhook = info_span!("webhook", platform = "gitcode", issue = "#3");
+            let _webhook = webhook.enter();
+            let command = info_span!("command", command = "run", experiment = field::Empty);
+            command.record("experiment", "pr-org-repo-3");
+            let _command = command.enter();
+            info!(latency = 12u64, "Creating experiment");
+        });
+
+        let line: Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(line["message"], "Creating experiment");
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["request_id"], "req-1");
+        assert_eq!(line["platform"], "gitcode");
+        assert_eq!(line["issue"], "#3");
+        assert_eq!(line["command"], "run");
+        assert_eq!(line["experiment"], "pr-org-repo-3");
+        assert_eq!(line["latency"], 12);
+        assert!(line["timestamp"].is_string());
+    }
+
+    #[test]
+    fn test_json_inner_fields_win() {
+        let output = capture(LogFormat::Json, || {
+            let outer = info_span!("outer", project = "org/a");
+            let _outer = outer.enter();
+            let inner = info_span!("inner", project = "org/b");
+            let _inner = inner.enter();
+            info!("event");
+            info!(project = "org/c", "event");
+        });
+
+        let lines: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines[0]["project"], "org/b");
+        assert_eq!(lines[1]["project"], "org/c");
+    }
+
+    #[test]
+    fn test_text() {
+        let output = capture(LogFormat::Text, || {
+            let span = info_span!("request", request_id = "req-1");
+            let _span = span.enter();
+            info!("hello");
+        });
+        assert!(output.contains("request{request_id=\"req-1\"}: "));
+        assert!(output.ends_with("hello\n"));
+    }
+}
@@ -0,0 +1,69 @@
+//! Counters served at `GET /metrics` in the Prometheus text format
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Requests rejected by the allow-list of a route, by route
+    rejected_sources: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a request to `route` from a source outside its allow-list
+    pub fn record_rejected_source(&self, route: &'static str) {
+        *self
+            .rejected_sources
+            .lock()
+            .unwrap()
+            .entry(route)
+            .or_default() += 1;
+    }
+
+    pub fn rejected_sources(&self, route: &str) -> u64 {
+        let rejected = self.rejected_sources.lock().unwrap();
+        rejected.get(route).copied().unwrap_or(0)
+    }
+
+    /// All counters in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        output.push_str(
+            "# HELP crater_ohos_bot_rejected_sources_total Requests rejected because \
+             their source address is not allowed.\n",
+        );
+        output.push_str("# TYPE crater_ohos_bot_rejected_sources_total counter\n");
+        for (route, count) in self.rejected_sources.lock().unwrap().iter() {
+            let _ = writeln!(
+                output,
+                "crater_ohos_bot_rejected_sources_total{{route=\"{}\"}} {}",
+                route, count
+            );
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.record_rejected_source("webhook");
+        metrics.record_rejected_source("webhook");
+        metrics.record_rejected_source("callback");
+
+        assert_eq!(metrics.rejected_sources("webhook"), 2);
+        assert_eq!(metrics.rejected_sources("audit"), 0);
+        let output = metrics.render();
+        assert!(output.contains("# TYPE crater_ohos_bot_rejected_sources_total counter\n"));
+        assert!(output.contains("crater_ohos_bot_rejected_sources_total{route=\"webhook\"} 2\n"));
+        assert!(output.contains("crater_ohos_bot_rejected_sources_total{route=\"callback\"} 1\n"));
+    }
+}
@@ -1,56 +1,88 @@
-use crate::config::PlatformConfig;
+use crate::config::{Config, HttpConfig, PlatformConfig};
 use crate::error::{BotError, Result};
-use crate::platforms::PlatformAdapter;
+use crate::http;
+use crate::platforms::{ExperimentMapping, MergeRequestInfo, PlatformAdapter, Thread};
+use crate::reload::Reloadable;
 use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::{debug, info, warn};
+use tracing::{debug, info, instrument, warn};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct CreateCommentRequest {
     body: String,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+struct PullRequestRef {
+    sha: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PullRequest {
+    head: PullRequestRef,
+    base: PullRequestRef,
+    #[serde(default)]
+    merge_commit_sha: Option<String>,
+}
+
 pub struct GitCodeAdapter {
     client: Client,
-    config: PlatformConfig,
-    // Simple in-memory storage for experiment mappings
-    // WARNING: This will lose all mappings on restart!
-    // TODO: Replace with persistent storage (database or file-based) for production use
-    experiment_mappings: Arc<RwLock<HashMap<String, String>>>,
+    config: std::sync::RwLock<PlatformConfig>,
+    // Kept in memory; saved to `mapping_store` on every change, if configured
+    experiment_mappings: Arc<RwLock<HashMap<String, ExperimentMapping>>>,
+    mapping_store: Option<PathBuf>,
 }
 
 impl GitCodeAdapter {
-    pub fn new(config: PlatformConfig) -> Result<Self> {
-        let client = Client::builder()
-            .build()
-            .map_err(|e| BotError::Internal(format!("Failed to create HTTP client: {}", e)))?;
-
+    /// Create the adapter, loading the mappings saved in `config.mapping_store`
+    pub fn new(config: PlatformConfig, http: &HttpConfig) -> Result<Self> {
+        let mapping_store = config.mapping_store.as_ref().map(PathBuf::from);
+        let mappings = match &mapping_store {
+            Some(path) => load_mappings(path)?,
+            None => HashMap::new(),
+        };
         Ok(Self {
-            client,
-            config,
-            experiment_mappings: Arc::new(RwLock::new(HashMap::new())),
+            client: http::client(http)?,
+            config: std::sync::RwLock::new(config),
+            experiment_mappings: Arc::new(RwLock::new(mappings)),
+            mapping_store,
         })
     }
 
-    fn make_mapping_key(&self, project: &str, issue_id: u64) -> String {
-        format!("{}#{}", project, issue_id)
+    fn make_mapping_key(&self, project: &str, thread: Thread) -> String {
+        format!("{}{}", project, thread)
     }
 }
 
 #[async_trait]
 impl PlatformAdapter for GitCodeAdapter {
-    async fn post_comment(&self, project: &str, issue_id: u64, content: &str) -> Result<()> {
+    #[instrument(
+        name = "gitcode.post_comment",
+        skip_all,
+        fields(project = %project, issue = %thread)
+    )]
+    async fn post_comment(&self, project: &str, thread: Thread, content: &str) -> Result<()> {
         // GitCode API: POST /api/v5/repos/{owner}/{repo}/issues/{number}/comments
-        let url = format!(
-            "{}/repos/{}/issues/{}/comments",
-            self.config.api_url, project, issue_id
-        );
+        //          or: POST /api/v5/repos/{owner}/{repo}/pulls/{number}/comments
+        let (api_url, access_token) = {
+            let config = self.config.read().unwrap();
+            (config.api_url.clone(), config.access_token.clone())
+        };
+        let url = match thread {
+            Thread::Issue(number) => {
+                format!("{}/repos/{}/issues/{}/comments", api_url, project, number)
+            }
+            Thread::MergeRequest(number) => {
+                format!("{}/repos/{}/pulls/{}/comments", api_url, project, number)
+            }
+        };
 
-        info!("Posting comment to GitCode issue: {}/{}", project, issue_id);
+        info!("Posting comment to GitCode: {}{}", project, thread);
         debug!("Comment content: {}", content);
 
         let request = CreateCommentRequest {
@@ -61,7 +93,7 @@ impl PlatformAdapter for GitCodeAdapter {
             .client
             .post(&url)
             .header("Content-Type", "application/json")
-            .header("Authorization", format!("token {}", &self.config.access_token))
+            .header("Authorization", format!("token {}", access_token))
             .json(&request)
             .send()
             .await?;
@@ -84,7 +116,8 @@ impl PlatformAdapter for GitCodeAdapter {
         // Use constant-time comparison to prevent timing attacks
         use subtle::ConstantTimeEq;
         
-        let expected = self.config.webhook_secret.as_bytes();
+        let config = self.config.read().unwrap();
+        let expected = config.webhook_secret.as_bytes();
         let provided = signature.as_bytes();
         
         if expected.len() != provided.len() {
@@ -95,22 +128,197 @@ impl PlatformAdapter for GitCodeAdapter {
         Ok(expected.ct_eq(provided).into())
     }
 
+    #[instrument(name = "gitcode.store_experiment_mapping", skip_all)]
     async fn store_experiment_mapping(
         &self,
         project: &str,
-        issue_id: u64,
-        experiment_name: &str,
+        thread: Thread,
+        mapping: ExperimentMapping,
     ) -> Result<()> {
-        let key = self.make_mapping_key(project, issue_id);
+        let key = self.make_mapping_key(project, thread);
         let mut mappings = self.experiment_mappings.write().await;
-        mappings.insert(key, experiment_name.to_string());
+        mappings.insert(key, mapping);
+        // Saved while still holding the lock so concurrent saves cannot reorder
+        if let Some(path) = &self.mapping_store {
+            save_mappings(path, &mappings).await?;
+        }
         Ok(())
     }
 
-    async fn get_experiment_mapping(&self, project: &str, issue_id: u64) -> Result<Option<String>> {
-        let key = self.make_mapping_key(project, issue_id);
+    #[instrument(name = "gitcode.get_experiment_mapping", skip_all)]
+    async fn get_experiment_mapping(
+        &self,
+        project: &str,
+        thread: Thread,
+    ) -> Result<Option<ExperimentMapping>> {
+        let key = self.make_mapping_key(project, thread);
         let mappings = self.experiment_mappings.read().await;
         Ok(mappings.get(&key).cloned())
     }
+
+    #[instrument(
+        name = "gitcode.get_merge_request",
+        skip_all,
+        fields(project = %project, issue = %Thread::MergeRequest(number))
+    )]
+    async fn get_merge_request(&self, project: &str, number: u64) -> Result<MergeRequestInfo> {
+        // GitCode API: GET /api/v5/repos/{owner}/{repo}/pulls/{number}
+        let (api_url, access_token) = {
+            let config = self.config.read().unwrap();
+            (config.api_url.clone(), config.access_token.clone())
+        };
+        let url = format!("{}/repos/{}/pulls/{}", api_url, project, number);
+
+        info!("Fetching GitCode merge request: {}!{}", project, number);
+
+        let response = self
+            .client
+            .get(&url)
+            .header("Authorization", format!("token {}", access_token))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(BotError::Platform(format!(
+                "Failed to fetch merge request from GitCode: {} - {}",
+                status, body
+            )));
+        }
+
+        let pull = response.json::<PullRequest>().await?;
+        Ok(MergeRequestInfo {
+            head_sha: pull.head.sha,
+            base_sha: pull.base.sha,
+            merge_commit_sha: pull.merge_commit_sha.filter(|sha| !sha.is_empty()),
+        })
+    }
+
+    async fn flush(&self) -> Result<()> {
+        let mappings = self.experiment_mappings.read().await;
+        let Some(path) = &self.mapping_store else {
+            if !mappings.is_empty() {
+                warn!(
+                    "Shutting down with {} experiment mappings and no mapping_store; they will not survive the restart",
+                    mappings.len()
+                );
+            }
+            return Ok(());
+        };
+
+        save_mappings(path, &mappings).await?;
+        info!(
+            "Saved {} experiment mappings to {}",
+            mappings.len(),
+            path.display()
+        );
+        Ok(())
+    }
+}
+
+/// Mappings saved by a previous run; none when the store does not exist yet
+/// Replace the store in one step so an interrupted write never truncates it
+async fn save_mappings(path: &Path, mappings: &HashMap<String, ExperimentMapping>) -> Result<()> {
+    let temporary = path.with_extension("tmp");
+    tokio::fs::write(&temporary, serde_json::to_vec_pretty(mappings)?).await?;
+    tokio::fs::rename(&temporary, path).await?;
+    Ok(())
 }
 
+fn load_mappings(path: &Path) -> Result<HashMap<String, ExperimentMapping>> {
+    let contents = match std::fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => {
+            return Err(BotError::Config(format!(
+                "Cannot read experiment mappings from {}: {}",
+                path.display(),
+                e
+            )))
+        }
+    };
+    let mappings: HashMap<String, ExperimentMapping> =
+        serde_json::from_slice(&contents).map_err(|e| {
+            BotError::Config(format!(
+                "Invalid experiment mappings in {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+    info!(
+        "Loaded {} experiment mappings from {}",
+        mappings.len(),
+        path.display()
+    );
+    Ok(mappings)
+}
+
+impl Reloadable for GitCodeAdapter {
+    fn reload(&self, config: &Config) {
+        if let Some(gitcode) = &config.platforms.gitcode {
+            *self.config.write().unwrap() = gitcode.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(mapping_store: Option<String>) -> PlatformConfig {
+        PlatformConfig {
+            enabled: true,
+            api_url: "http://localhost:1".to_string(),
+            access_token: String::new(),
+            access_token_file: None,
+            webhook_secret: String::new(),
+            webhook_secret_file: None,
+            bot_username: None,
+            ignored_users: Vec::new(),
+            mapping_store,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mappings_survive_restart() {
+        let dir = std::env::temp_dir().join(format!(
+            "crater-ohos-bot-{}-mapping-store",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = dir.join("mappings.json").to_string_lossy().into_owned();
+        let mapping = ExperimentMapping {
+            experiment: "pr-org-repo-3".to_string(),
+            request_id: Some("req-1".to_string()),
+        };
+
+        let adapter =
+            GitCodeAdapter::new(config(Some(store.clone())), &HttpConfig::default()).unwrap();
+        adapter
+            .store_experiment_mapping("org/repo", Thread::MergeRequest(3), mapping.clone())
+            .await
+            .unwrap();
+        // Saved without a flush, as after a crash
+        drop(adapter);
+
+        let restarted =
+            GitCodeAdapter::new(config(Some(store.clone())), &HttpConfig::default()).unwrap();
+        let loaded = restarted
+            .get_experiment_mapping("org/repo", Thread::MergeRequest(3))
+            .await
+            .unwrap();
+        assert_eq!(loaded, Some(mapping));
+        assert_eq!(
+            restarted
+                .get_experiment_mapping("org/repo", Thread::Issue(3))
+                .await
+                .unwrap(),
+            None
+        );
+
+        std::fs::write(&store, "not json").unwrap();
+        assert!(GitCodeAdapter::new(config(Some(store)), &HttpConfig::default()).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
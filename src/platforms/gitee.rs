@@ -1,6 +1,6 @@
 use crate::config::PlatformConfig;
 use crate::error::{BotError, Result};
-use crate::platforms::PlatformAdapter;
+use crate::platforms::{ExperimentMapping, PlatformAdapter, Thread};
 use async_trait::async_trait;
 
 pub struct GiteeAdapter {
@@ -15,7 +15,7 @@ impl GiteeAdapter {
 
 #[async_trait]
 impl PlatformAdapter for GiteeAdapter {
-    async fn post_comment(&self, _project: &str, _issue_id: u64, _content: &str) -> Result<()> {
+    async fn post_comment(&self, _project: &str, _thread: Thread, _content: &str) -> Result<()> {
         Err(BotError::Platform(
             "Gitee adapter not implemented yet".to_string(),
         ))
@@ -30,8 +30,8 @@ impl PlatformAdapter for GiteeAdapter {
     async fn store_experiment_mapping(
         &self,
         _project: &str,
-        _issue_id: u64,
-        _experiment_name: &str,
+        _thread: Thread,
+        _mapping: ExperimentMapping,
     ) -> Result<()> {
         Err(BotError::Platform(
             "Gitee adapter not implemented yet".to_string(),
@@ -41,8 +41,8 @@ impl PlatformAdapter for GiteeAdapter {
     async fn get_experiment_mapping(
         &self,
         _project: &str,
-        _issue_id: u64,
-    ) -> Result<Option<String>> {
+        _thread: Thread,
+    ) -> Result<Option<ExperimentMapping>> {
         Err(BotError::Platform(
             "Gitee adapter not implemented yet".to_string(),
         ))
@@ -2,25 +2,92 @@ pub mod gitcode;
 pub mod github;
 pub mod gitee;
 
-use crate::error::Result;
+use crate::error::{BotError, Result};
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The discussion a command was posted in: an issue or a merge/pull request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Thread {
+    Issue(u64),
+    MergeRequest(u64),
+}
+
+impl Thread {
+    /// The issue or merge request number within its project
+    pub fn number(&self) -> u64 {
+        match self {
+            Thread::Issue(number) | Thread::MergeRequest(number) => *number,
+        }
+    }
+
+    pub fn is_merge_request(&self) -> bool {
+        matches!(self, Thread::MergeRequest(_))
+    }
+}
+
+impl fmt::Display for Thread {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Thread::Issue(number) => write!(f, "#{}", number),
+            Thread::MergeRequest(number) => write!(f, "!{}", number),
+        }
+    }
+}
+
+/// The experiment started from an issue or merge request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExperimentMapping {
+    pub experiment: String,
+    /// ID of the request whose command started the experiment
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+/// Commits of a merge request, as reported by the platform
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeRequestInfo {
+    /// Latest commit of the source branch
+    pub head_sha: String,
+    /// Commit of the target branch the merge request is based on
+    pub base_sha: String,
+    /// Test merge commit, if the platform has created one
+    pub merge_commit_sha: Option<String>,
+}
 
 #[async_trait]
 pub trait PlatformAdapter: Send + Sync {
-    /// Post a comment to an issue
-    async fn post_comment(&self, project: &str, issue_id: u64, content: &str) -> Result<()>;
+    /// Post a comment to an issue or merge request
+    async fn post_comment(&self, project: &str, thread: Thread, content: &str) -> Result<()>;
 
     /// Verify webhook signature
     fn verify_webhook(&self, payload: &[u8], signature: &str) -> Result<bool>;
 
-    /// Store experiment mapping for an issue
+    /// Store experiment mapping for an issue or merge request
     async fn store_experiment_mapping(
         &self,
         project: &str,
-        issue_id: u64,
-        experiment_name: &str,
+        thread: Thread,
+        mapping: ExperimentMapping,
     ) -> Result<()>;
 
-    /// Get experiment mapping for an issue
-    async fn get_experiment_mapping(&self, project: &str, issue_id: u64) -> Result<Option<String>>;
+    /// Get experiment mapping for an issue or merge request
+    async fn get_experiment_mapping(
+        &self,
+        project: &str,
+        thread: Thread,
+    ) -> Result<Option<ExperimentMapping>>;
+
+    /// Look up the commits of a merge request
+    async fn get_merge_request(&self, _project: &str, _number: u64) -> Result<MergeRequestInfo> {
+        Err(BotError::Platform(
+            "Merge request lookup is not supported on this platform".to_string(),
+        ))
+    }
+
+    /// Persist any buffered state before shutdown
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
 }
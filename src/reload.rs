@@ -0,0 +1,279 @@
+//! Hot reloading of configuration without a restart
+use crate::config::Config;
+use crate::error::Result;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+/// How often the config file's modification time is checked
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Extensions tried when the configured path has none, mirroring `config::File::with_name`
+const CONFIG_EXTENSIONS: &[&str] = &["toml", "json", "yaml", "yml", "ini", "ron", "json5"];
+
+/// A component that can pick up a new configuration without a restart
+pub trait Reloadable: Send + Sync {
+    /// Swap in the reloadable parts of `config`
+    fn reload(&self, config: &Config);
+}
+
+/// Watches the configuration file and pushes validated changes to registered components
+pub struct ConfigReloader {
+    path: String,
+    current: Mutex<Config>,
+    targets: Vec<Arc<dyn Reloadable>>,
+}
+
+impl ConfigReloader {
+    pub fn new(path: impl Into<String>, config: Config) -> Self {
+        Self {
+            path: path.into(),
+            current: Mutex::new(config),
+            targets: Vec::new(),
+        }
+    }
+
+    pub fn register(mut self, target: Arc<dyn Reloadable>) -> Self {
+        self.targets.push(target);
+        self
+    }
+
+    /// Load and validate the config file, apply it to every registered component
+    /// and return the keys that changed. On error the running config is kept.
+    pub fn reload(&self) -> Result<Vec<String>> {
+        let new_config = Config::from_file(&self.path)?;
+
+        let mut current = self.current.lock().unwrap();
+        let changed = changed_keys(&current, &new_config);
+        if changed.is_empty() {
+            return Ok(changed);
+        }
+
+        let restart_required: Vec<&String> =
+            changed.iter().filter(|key| requires_restart(key)).collect();
+        if !restart_required.is_empty() {
+            warn!(
+                "Config changes that only take effect after a restart: {:?}",
+                restart_required
+            );
+        }
+
+        for target in &self.targets {
+            target.reload(&new_config);
+        }
+        *current = new_config;
+
+        info!("Configuration reloaded, changed keys: {:?}", changed);
+        Ok(changed)
+    }
+
+    /// Reload whenever the config file changes on disk or the process receives SIGHUP
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let file = resolve_path(&self.path);
+            let mut last_modified = file.as_deref().and_then(modified_time);
+            let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+            #[cfg(unix)]
+            let mut hangup =
+                match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+                    Ok(signal) => Some(signal),
+                    Err(e) => {
+                        warn!("Cannot install SIGHUP handler: {}", e);
+                        None
+                    }
+                };
+
+            loop {
+                #[cfg(unix)]
+                let hangup_received = async {
+                    match hangup.as_mut() {
+                        Some(signal) => signal.recv().await,
+                        None => std::future::pending().await,
+                    }
+                };
+                #[cfg(not(unix))]
+                let hangup_received = std::future::pending::<Option<()>>();
+
+                tokio::select! {
+                    _ = interval.tick() => {
+                        let modified = file.as_deref().and_then(modified_time);
+                        if modified == last_modified {
+                            continue;
+                        }
+                        last_modified = modified;
+                        info!("Config file changed, reloading");
+                    }
+                    _ = hangup_received => {
+                        info!("Received SIGHUP, reloading configuration");
+                    }
+                }
+
+                if let Err(e) = self.reload() {
+                    error!(
+                        "Config reload rejected, keeping current configuration: {}",
+                        e
+                    );
+                }
+            }
+        })
+    }
+}
+
+/// Keys that are read once at startup (listener address, router state, which adapters
+/// exist, logging and the HTTP clients)
+fn requires_restart(key: &str) -> bool {
+    key.starts_with("server.")
+        || key.starts_with("crater.callback_secret")
+        || key.starts_with("audit.")
+        || key.starts_with("logging.")
+        || key.starts_with("http.")
+        || (key.starts_with("platforms.")
+            && (key.ends_with(".enabled") || key.ends_with(".mapping_store")))
+}
+
+/// Dotted paths of every leaf value that differs between two configs.
+/// Only key names are reported so secrets never reach the logs.
+pub fn changed_keys(old: &Config, new: &Config) -> Vec<String> {
+    let old = flatten(old);
+    let new = flatten(new);
+
+    let mut keys: Vec<String> = old
+        .keys()
+        .chain(new.keys())
+        .filter(|key| old.get(*key) != new.get(*key))
+        .cloned()
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+fn flatten(config: &Config) -> BTreeMap<String, Value> {
+    let mut out = BTreeMap::new();
+    if let Ok(value) = serde_json::to_value(config) {
+        flatten_into(String::new(), value, &mut out);
+    }
+    out
+}
+
+fn flatten_into(prefix: String, value: Value, out: &mut BTreeMap<String, Value>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let path = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten_into(path, value, out);
+            }
+        }
+        value => {
+            out.insert(prefix, value);
+        }
+    }
+}
+
+fn resolve_path(path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    if path.is_file() {
+        return Some(path.to_path_buf());
+    }
+    CONFIG_EXTENSIONS
+        .iter()
+        .map(|ext| path.with_extension(ext))
+        .find(|candidate| candidate.is_file())
+}
+
+pub(crate) fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const CONFIG: &str = r#"
+[server]
+host = "127.0.0.1"
+port = 8080
+
+[crater]
+api_url = "http://localhost:3000"
+api_token = "token"
+callback_base_url = "http://localhost:8080"
+
+[platforms]
+
+[bot]
+name = "crater-bot"
+trigger_prefix = "@crater-bot"
+default_mode = "build-and-test"
+default_crate_select = "demo"
+"#;
+
+    #[derive(Default)]
+    struct Counter(AtomicUsize);
+
+    impl Reloadable for Counter {
+        fn reload(&self, _config: &Config) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn write_config(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "crater-ohos-bot-reload-{}-{}.toml",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_reload_applies_changes() {
+        let path = write_config("applies", CONFIG);
+        let counter = Arc::new(Counter::default());
+        let reloader = ConfigReloader::new(path.clone(), Config::from_file(&path).unwrap())
+            .register(counter.clone());
+
+        assert!(reloader.reload().unwrap().is_empty());
+        assert_eq!(counter.0.load(Ordering::SeqCst), 0);
+
+        std::fs::write(&path, CONFIG.replace("@crater-bot", "@crater")).unwrap();
+        let changed = reloader.reload().unwrap();
+        assert_eq!(changed, vec!["bot.trigger_prefix".to_string()]);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_invalid_config_is_rejected() {
+        let path = write_config("invalid", CONFIG);
+        let counter = Arc::new(Counter::default());
+        let reloader = ConfigReloader::new(path.clone(), Config::from_file(&path).unwrap())
+            .register(counter.clone());
+
+        std::fs::write(&path, CONFIG.replace("api_token = \"token\"\n", "")).unwrap();
+        assert!(reloader.reload().is_err());
+        assert_eq!(counter.0.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_requires_restart() {
+        assert!(requires_restart("server.port"));
+        assert!(requires_restart("platforms.gitcode.enabled"));
+        assert!(requires_restart("platforms.gitcode.mapping_store"));
+        assert!(requires_restart("crater.callback_secret"));
+        assert!(requires_restart("logging.format"));
+        assert!(requires_restart("http.timeout_secs"));
+        assert!(!requires_restart("platforms.gitcode.access_token"));
+        assert!(!requires_restart("bot.default_mode"));
+    }
+}
@@ -0,0 +1,96 @@
+//! Request IDs tying together the logs, crater-ohos calls, experiment mappings and
+//! error comments that result from one incoming webhook or callback
+use crate::telemetry;
+use axum::{extract::Request, http::HeaderValue, middleware::Next, response::Response};
+use std::future::Future;
+use std::time::Instant;
+use tracing::{info, info_span, Instrument};
+
+/// Header carrying the request ID, both incoming and towards crater-ohos
+pub const HEADER: &str = "x-request-id";
+
+/// Longest request ID accepted from a client
+const MAX_LEN: usize = 64;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// ID of the request being handled by the current task, if any
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Run `future` as part of the request `id`
+pub async fn scope<F: Future>(id: String, future: F) -> F::Output {
+    REQUEST_ID.scope(id, future).await
+}
+
+/// A new random request ID
+pub fn generate() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// A client-supplied ID, if it is short and only uses characters safe to log and echo
+fn accept(value: &HeaderValue) -> Option<String> {
+    let id = value.to_str().ok()?;
+    let safe = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.');
+    (!id.is_empty() && id.len() <= MAX_LEN && id.chars().all(safe)).then(|| id.to_string())
+}
+
+/// Middleware assigning every request an ID: the client's `X-Request-Id` if
+/// acceptable, otherwise a new one. The ID is a field of the request's tracing span,
+/// available to handlers through [`current`] and returned in the response. The
+/// request's status and latency are logged when it completes.
+pub async fn propagate(request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let id = request
+        .headers()
+        .get(HEADER)
+        .and_then(accept)
+        .unwrap_or_else(generate);
+    let span = info_span!(
+        "request",
+        request_id = %id,
+        method = %request.method(),
+        path = %request.uri().path(),
+    );
+    telemetry::set_parent(&span, request.headers());
+
+    let mut response = scope(id.clone(), next.run(request))
+        .instrument(span.clone())
+        .await;
+    span.in_scope(|| {
+        info!(
+            status = response.status().as_u16(),
+            latency = started.elapsed().as_millis() as u64,
+            "Request completed"
+        )
+    });
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(HEADER, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept() {
+        let accepted = |id: &str| accept(&HeaderValue::from_str(id).unwrap());
+        assert_eq!(accepted("req-1.a_b"), Some("req-1.a_b".to_string()));
+        assert_eq!(accepted(""), None);
+        assert_eq!(accepted("a b"), None);
+        assert_eq!(accepted("<script>"), None);
+        assert_eq!(accepted(&"a".repeat(MAX_LEN + 1)), None);
+    }
+
+    #[tokio::test]
+    async fn test_current() {
+        assert_eq!(current(), None);
+        let id = scope("abc".to_string(), async { current() }).await;
+        assert_eq!(id, Some("abc".to_string()));
+    }
+}
@@ -0,0 +1,361 @@
+//! Wiring of the bot's components into an HTTP server
+use crate::api::{create_router, AppState};
+use crate::audit::AuditLog;
+use crate::bot::CommandProcessor;
+use crate::config::Config;
+use crate::crater::CraterClient;
+use crate::error::{BotError, Result};
+use crate::metrics::Metrics;
+use crate::platforms::{gitcode::GitCodeAdapter, PlatformAdapter};
+use crate::reload::{ConfigReloader, POLL_INTERVAL};
+use crate::templates::Templates;
+use crate::tls;
+use crate::webhook::{CallbackHandler, WebhookReceiver};
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use axum_server::Handle;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio_util::task::TaskTracker;
+use tracing::{error, info, warn};
+
+/// The assembled application: the router plus the reloader wired to its components
+pub struct App {
+    pub router: Router,
+    pub reloader: ConfigReloader,
+    /// Adapters flushed on shutdown
+    pub platforms: Vec<Arc<dyn PlatformAdapter>>,
+    /// Webhook processing tasks, which may outlive their request
+    pub tasks: TaskTracker,
+}
+
+impl App {
+    /// Build every component from `config`. `config_path` is the file watched for reloads.
+    pub fn build(config: &Config, config_path: &str) -> Result<Self> {
+        // Initialize crater client
+        let crater_client = Arc::new(CraterClient::new(config.crater.clone(), &config.http)?);
+        info!("Crater client initialized");
+
+        // Load comment templates
+        let templates = Arc::new(Templates::new(&config.templates)?);
+        info!("Comment templates loaded");
+
+        // Initialize command processor
+        let command_processor = Arc::new(CommandProcessor::new(
+            crater_client.clone(),
+            config,
+            templates.clone(),
+        ));
+        info!("Command processor initialized");
+
+        // Initialize platform adapters
+        let gitcode_adapter = match &config.platforms.gitcode {
+            Some(gitcode_config) if gitcode_config.enabled => {
+                Arc::new(GitCodeAdapter::new(gitcode_config.clone(), &config.http)?)
+            }
+            _ => return Err(BotError::Config("GitCode adapter not enabled".to_string())),
+        };
+        info!("GitCode adapter initialized");
+
+        // Initialize audit log
+        let audit_log = Arc::new(AuditLog::new(config.audit.clone())?);
+        if audit_log.is_enabled() {
+            info!("Audit log enabled at {}", config.audit.path);
+        }
+
+        // Initialize webhook receiver
+        let webhook_receiver = Arc::new(WebhookReceiver::new(
+            command_processor.clone(),
+            gitcode_adapter.clone(),
+            config.clone(),
+            audit_log.clone(),
+            templates.clone(),
+        ));
+        info!("Webhook receiver initialized");
+
+        // Initialize callback handler
+        let callback_handler = Arc::new(CallbackHandler::new(
+            gitcode_adapter.clone(),
+            config,
+            templates.clone(),
+        ));
+        info!("Callback handler initialized");
+
+        // Components that pick up config changes without a restart
+        let reloader = ConfigReloader::new(config_path, config.clone())
+            .register(crater_client)
+            .register(command_processor)
+            .register(webhook_receiver.clone())
+            .register(callback_handler.clone())
+            .register(templates)
+            .register(gitcode_adapter.clone());
+
+        let tasks = TaskTracker::new();
+        let app_state = AppState {
+            webhook_receiver,
+            callback_handler,
+            callback_secret: config.crater.callback_secret.clone(),
+            audit_log,
+            audit_query_token: config.audit.query_token.clone(),
+            metrics: Arc::new(Metrics::new()),
+            tasks: tasks.clone(),
+        };
+
+        Ok(Self {
+            router: create_router(app_state, &config.server),
+            reloader,
+            platforms: vec![gitcode_adapter],
+            tasks,
+        })
+    }
+}
+
+/// Run the bot until a shutdown signal arrives. `bind` overrides `server.host`/`server.port`.
+pub async fn serve(config_path: &str, bind: Option<String>) -> Result<()> {
+    let config = Config::from_file(config_path)?;
+    info!("Configuration loaded");
+
+    let App {
+        router,
+        reloader,
+        platforms,
+        tasks,
+    } = App::build(&config, config_path)?;
+
+    // Watch the config file and apply changes without a restart
+    let reloader = Arc::new(reloader).spawn();
+    info!("Config reloader started");
+
+    let addr = bind.unwrap_or_else(|| format!("{}:{}", config.server.host, config.server.port));
+    info!("Starting server on {}", addr);
+
+    let listener = TcpListener::bind(&addr).await?;
+    let drain_timeout = Duration::from_secs(config.server.shutdown_timeout_secs);
+    let mut workers = vec![reloader];
+    let result = match &config.server.tls {
+        Some(tls_config) => {
+            let local_addr = listener.local_addr()?;
+            let tls = tls::load(tls_config).await?;
+            workers.push(tls::watch(tls_config.clone(), tls.clone(), POLL_INTERVAL));
+            if let Some(port) = tls_config.redirect_http_port {
+                let redirect = tls::spawn_redirect(local_addr.ip(), port, local_addr.port());
+                workers.push(redirect.await?);
+            }
+            info!("Serving HTTPS");
+            serve_tls_until(listener, router, tls, shutdown_signal(), drain_timeout).await
+        }
+        None => serve_until(listener, router, shutdown_signal(), drain_timeout).await,
+    };
+
+    // Commands whose request timed out keep running after the server has drained
+    tasks.close();
+    if tokio::time::timeout(drain_timeout, tasks.wait()).await.is_err() {
+        warn!(
+            "{} commands still running after {:?}, shutting down anyway",
+            tasks.len(),
+            drain_timeout
+        );
+    }
+
+    // Background workers are stopped only after in-flight handlers have finished
+    for worker in workers {
+        worker.abort();
+    }
+    for platform in &platforms {
+        if let Err(e) = platform.flush().await {
+            error!("Failed to flush platform state: {}", e);
+        }
+    }
+    info!("Shutdown complete");
+
+    result
+}
+
+/// Serve `router` until `signal` resolves, then stop accepting connections and wait
+/// up to `drain_timeout` for in-flight requests to finish.
+pub async fn serve_until(
+    listener: TcpListener,
+    router: Router,
+    signal: impl Future<Output = ()> + Send + 'static,
+    drain_timeout: Duration,
+) -> Result<()> {
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(async move {
+        axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(async move {
+            let _ = stop_rx.await;
+        })
+        .await
+    });
+
+    drain(
+        server,
+        move || {
+            let _ = stop_tx.send(());
+        },
+        signal,
+        drain_timeout,
+    )
+    .await
+}
+
+/// Like [`serve_until`], serving HTTPS with the certificate of `tls`
+pub async fn serve_tls_until(
+    listener: TcpListener,
+    router: Router,
+    tls: RustlsConfig,
+    signal: impl Future<Output = ()> + Send + 'static,
+    drain_timeout: Duration,
+) -> Result<()> {
+    let handle = Handle::new();
+    let server = tokio::spawn(
+        axum_server::from_tcp_rustls(listener.into_std()?, tls)
+            .handle(handle.clone())
+            .serve(router.into_make_service_with_connect_info::<SocketAddr>()),
+    );
+
+    drain(
+        server,
+        move || handle.graceful_shutdown(None),
+        signal,
+        drain_timeout,
+    )
+    .await
+}
+
+/// Wait for `server` to exit or `signal` to resolve. On the signal, `stop` makes the
+/// server stop accepting connections and in-flight requests get `drain_timeout` to finish.
+async fn drain(
+    mut server: JoinHandle<std::io::Result<()>>,
+    stop: impl FnOnce(),
+    signal: impl Future<Output = ()>,
+    drain_timeout: Duration,
+) -> Result<()> {
+    tokio::select! {
+        result = &mut server => {
+            return result
+                .map_err(|e| BotError::Internal(format!("Server task failed: {}", e)))?
+                .map_err(Into::into);
+        }
+        _ = signal => {
+            info!("Shutdown signal received, draining in-flight requests");
+            stop();
+        }
+    }
+
+    match tokio::time::timeout(drain_timeout, &mut server).await {
+        Ok(result) => {
+            result.map_err(|e| BotError::Internal(format!("Server task failed: {}", e)))??;
+            info!("All in-flight requests drained");
+        }
+        Err(_) => {
+            warn!(
+                "In-flight requests still running after {:?}, shutting down anyway",
+                drain_timeout
+            );
+            server.abort();
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolves on Ctrl-C or SIGTERM
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Cannot listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Cannot listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, routing::get};
+    use std::sync::Arc;
+    use tokio::sync::{oneshot, Notify};
+
+    /// Signals that the request has reached the handler, then takes a while to finish
+    async fn slow(State(started): State<Arc<Notify>>) -> &'static str {
+        started.notify_one();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        "done"
+    }
+
+    async fn start_slow_request(
+        drain_timeout: Duration,
+    ) -> (
+        tokio::task::JoinHandle<reqwest::Result<reqwest::Response>>,
+        oneshot::Sender<()>,
+        tokio::task::JoinHandle<Result<()>>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/slow", listener.local_addr().unwrap());
+        let started = Arc::new(Notify::new());
+        let router = Router::new()
+            .route("/slow", get(slow))
+            .with_state(started.clone());
+        let (tx, rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(serve_until(
+            listener,
+            router,
+            async move {
+                let _ = rx.await;
+            },
+            drain_timeout,
+        ));
+
+        let request = tokio::spawn(reqwest::get(url));
+        started.notified().await;
+        (request, tx, server)
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_request_is_drained() {
+        let (request, tx, server) = start_slow_request(Duration::from_secs(5)).await;
+        tx.send(()).unwrap();
+
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.text().await.unwrap(), "done");
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_drain_timeout() {
+        let (request, tx, server) = start_slow_request(Duration::from_millis(10)).await;
+        tx.send(()).unwrap();
+
+        // serve_until returns without waiting for the slow request
+        server.await.unwrap().unwrap();
+        assert!(!request.is_finished());
+    }
+}
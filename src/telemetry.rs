@@ -0,0 +1,145 @@
+//! OpenTelemetry trace export and W3C trace-context propagation.
+//!
+//! With `[logging.otlp]` configured, the tracing spans of the bot are exported:
+//! `request` for every HTTP request, `webhook` and `command` for comments,
+//! `callback` for crater-ohos callbacks, `crater.*` for each crater-ohos API call
+//! and `gitcode.*` for each platform adapter call. Requests to crater-ohos carry a
+//! `traceparent` header, and incoming requests with one continue the caller's trace.
+use crate::config::OtlpConfig;
+use crate::error::{BotError, Result};
+use opentelemetry::propagation::{Extractor, Injector, TextMapPropagator};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use tracing::{Span, Subscriber};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Instrumentation scope of the exported spans
+const TRACER_NAME: &str = "crater-ohos-bot";
+
+/// Provider batching spans to the collector on a background thread. The exporter
+/// uses a blocking HTTP client, so create the provider outside the async runtime.
+pub fn tracer_provider(config: &OtlpConfig) -> Result<SdkTracerProvider> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(&config.endpoint)
+        .build()
+        .map_err(|e| BotError::Config(format!("Cannot set up trace export: {}", e)))?;
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            config.sample_ratio,
+        ))))
+        .with_resource(
+            Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .build(),
+        )
+        .build())
+}
+
+/// Layer turning tracing spans into OpenTelemetry spans of `provider`
+pub fn layer<S>(provider: &SdkTracerProvider) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(TRACER_NAME))
+}
+
+/// `traceparent` (and `tracestate`) of the current span, for an outgoing request.
+/// Empty when traces are not exported.
+pub fn trace_headers() -> reqwest::header::HeaderMap {
+    let mut headers = reqwest::header::HeaderMap::new();
+    TraceContextPropagator::new().inject_context(
+        &Span::current().context(),
+        &mut HeaderInjector(&mut headers),
+    );
+    headers
+}
+
+/// Make `span` a child of the trace named by the `traceparent` of an incoming request
+pub fn set_parent(span: &Span, headers: &axum::http::HeaderMap) {
+    let context = TraceContextPropagator::new().extract(&HeaderExtractor(headers));
+    // Fails only when spans are not exported, leaving nothing to link
+    let _ = span.set_parent(context);
+}
+
+struct HeaderInjector<'a>(&'a mut reqwest::header::HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            reqwest::header::HeaderName::from_bytes(key.as_bytes()),
+            reqwest::header::HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+struct HeaderExtractor<'a>(&'a axum::http::HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::TraceContextExt;
+    use opentelemetry_sdk::trace::InMemorySpanExporter;
+    use tracing::info_span;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_propagation() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry().with(layer(&provider));
+
+        let outgoing = tracing::subscriber::with_default(subscriber, || {
+            let mut incoming = axum::http::HeaderMap::new();
+            incoming.insert("traceparent", TRACEPARENT.parse().unwrap());
+            let span = info_span!("request");
+            set_parent(&span, &incoming);
+            let _entered = span.enter();
+            assert!(span.context().span().span_context().is_valid());
+            trace_headers()
+        });
+
+        // Same trace, with the request span as the parent
+        let traceparent = outgoing["traceparent"].to_str().unwrap();
+        assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        assert!(!traceparent.contains("00f067aa0ba902b7"));
+
+        let spans = exporter.get_finished_spans().unwrap();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].name, "request");
+        assert_eq!(
+            spans[0].parent_span_id.to_string(),
+            "00f067aa0ba902b7".to_string()
+        );
+    }
+
+    #[test]
+    fn test_no_headers_without_export() {
+        let span = info_span!("request");
+        let _entered = span.enter();
+        assert!(trace_headers().is_empty());
+    }
+}
@@ -0,0 +1,432 @@
+//! Handlebars templates of the comments announcing experiments and errors. Default
+//! templates for every locale are built in; a deployment may override any of them
+//! with `<dir>/<locale>/<name>.hbs` files.
+use crate::config::{Config, TemplatesConfig};
+use crate::error::{BotError, Result};
+use crate::i18n::Locale;
+use crate::reload::Reloadable;
+use handlebars::{handlebars_helper, Handlebars};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::RwLock;
+use tracing::{error, info};
+
+/// The context a template is rendered with. Each field is available to the
+/// template by name.
+pub trait MessageContext: Serialize {
+    /// Template name, also the file name without `.hbs`
+    const TEMPLATE: &'static str;
+
+    /// Example values used to check templates when they are loaded
+    fn sample() -> Self;
+}
+
+/// An experiment was created and started
+#[derive(Debug, Clone, Serialize)]
+pub struct RunStarted {
+    pub experiment: String,
+    pub toolchains: Vec<String>,
+    pub mode: String,
+    pub crates: String,
+    pub priority: i32,
+    /// Localized status, e.g. `运行中`
+    pub status: String,
+    pub project: String,
+    /// Author of the command
+    pub author: String,
+}
+
+impl MessageContext for RunStarted {
+    const TEMPLATE: &'static str = "run_started";
+
+    fn sample() -> Self {
+        Self {
+            experiment: "org--repo-1".to_string(),
+            toolchains: vec!["stable".to_string(), "beta".to_string()],
+            mode: "build-and-test".to_string(),
+            crates: "demo".to_string(),
+            priority: 0,
+            status: "queued".to_string(),
+            project: "org/repo".to_string(),
+            author: "alice".to_string(),
+        }
+    }
+}
+
+/// Reply to `status`
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusReport {
+    pub experiment: String,
+    pub toolchains: Vec<String>,
+    /// Localized status
+    pub status: String,
+    pub mode: String,
+    pub project: String,
+}
+
+impl MessageContext for StatusReport {
+    const TEMPLATE: &'static str = "status";
+
+    fn sample() -> Self {
+        Self {
+            experiment: "org--repo-1".to_string(),
+            toolchains: vec!["stable".to_string(), "beta".to_string()],
+            status: "running".to_string(),
+            mode: "build-and-test".to_string(),
+            project: "org/repo".to_string(),
+        }
+    }
+}
+
+/// crater reported an experiment as completed
+#[derive(Debug, Clone, Serialize)]
+pub struct Completed {
+    pub experiment: String,
+    pub report_url: Option<String>,
+    pub project: String,
+}
+
+impl MessageContext for Completed {
+    const TEMPLATE: &'static str = "completed";
+
+    fn sample() -> Self {
+        Self {
+            experiment: "org--repo-1".to_string(),
+            report_url: Some("https://crater.example.com/report/org--repo-1".to_string()),
+            project: "org/repo".to_string(),
+        }
+    }
+}
+
+/// crater reported an experiment as failed
+#[derive(Debug, Clone, Serialize)]
+pub struct Failed {
+    pub experiment: String,
+    pub project: String,
+}
+
+impl MessageContext for Failed {
+    const TEMPLATE: &'static str = "failed";
+
+    fn sample() -> Self {
+        Self {
+            experiment: "org--repo-1".to_string(),
+            project: "org/repo".to_string(),
+        }
+    }
+}
+
+/// A command failed
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorReply {
+    pub message: String,
+    /// For parse errors, the command line with a marker under the offending token
+    pub pointer: Option<String>,
+    /// For errors whose details are only logged, the ID to find them by
+    pub correlation_id: Option<String>,
+}
+
+impl MessageContext for ErrorReply {
+    const TEMPLATE: &'static str = "error";
+
+    fn sample() -> Self {
+        Self {
+            message: "Invalid command: unknown option 'colour' (column 29)".to_string(),
+            pointer: Some("@crater-bot run stable beta --colour red\n                            ^^^^^^^^^^^^".to_string()),
+            correlation_id: Some("5f0c6a1e-8b7d-4c1a-9e2f-3a4b5c6d7e8f".to_string()),
+        }
+    }
+}
+
+/// Names of all templates
+pub const TEMPLATE_NAMES: &[&str] = &[
+    RunStarted::TEMPLATE,
+    StatusReport::TEMPLATE,
+    Completed::TEMPLATE,
+    Failed::TEMPLATE,
+    ErrorReply::TEMPLATE,
+];
+
+/// Built-in templates by locale and name
+const BUILTIN: &[(Locale, &str, &str)] = &[
+    (
+        Locale::ZhCn,
+        "run_started",
+        include_str!("../templates/zh-CN/run_started.hbs"),
+    ),
+    (
+        Locale::ZhCn,
+        "status",
+        include_str!("../templates/zh-CN/status.hbs"),
+    ),
+    (
+        Locale::ZhCn,
+        "completed",
+        include_str!("../templates/zh-CN/completed.hbs"),
+    ),
+    (
+        Locale::ZhCn,
+        "failed",
+        include_str!("../templates/zh-CN/failed.hbs"),
+    ),
+    (
+        Locale::ZhCn,
+        "error",
+        include_str!("../templates/zh-CN/error.hbs"),
+    ),
+    (
+        Locale::En,
+        "run_started",
+        include_str!("../templates/en/run_started.hbs"),
+    ),
+    (
+        Locale::En,
+        "status",
+        include_str!("../templates/en/status.hbs"),
+    ),
+    (
+        Locale::En,
+        "completed",
+        include_str!("../templates/en/completed.hbs"),
+    ),
+    (
+        Locale::En,
+        "failed",
+        include_str!("../templates/en/failed.hbs"),
+    ),
+    (
+        Locale::En,
+        "error",
+        include_str!("../templates/en/error.hbs"),
+    ),
+];
+
+// `{{join toolchains " vs "}}`
+handlebars_helper!(join: |items: array, separator: str| {
+    items
+        .iter()
+        .map(|item| item.as_str().map(str::to_string).unwrap_or_else(|| item.to_string()))
+        .collect::<Vec<_>>()
+        .join(separator)
+});
+
+/// The templates in use: the built-in ones plus the configured overrides
+pub struct Templates {
+    registry: RwLock<Handlebars<'static>>,
+}
+
+impl Templates {
+    /// Load the built-in templates and the overrides in `config.dir`
+    pub fn new(config: &TemplatesConfig) -> Result<Self> {
+        Ok(Self {
+            registry: RwLock::new(load(config)?),
+        })
+    }
+
+    /// Render the comment for `context` in `locale`. An override that fails to
+    /// render, e.g. because it uses a field the context lacks, is logged and the
+    /// built-in template is used instead.
+    pub fn render<C: MessageContext>(&self, locale: Locale, context: &C) -> String {
+        let registry = self.registry.read().unwrap();
+        let rendered = registry
+            .render(&template_name(locale, C::TEMPLATE), context)
+            .or_else(|e| {
+                error!(
+                    "Failed to render template {}/{}: {}",
+                    locale.code(),
+                    C::TEMPLATE,
+                    e
+                );
+                registry.render(&builtin_name(locale, C::TEMPLATE), context)
+            })
+            .expect("built-in templates render every context");
+        // Template files end with a newline the comment does not need
+        rendered.trim_end().to_string()
+    }
+}
+
+impl Reloadable for Templates {
+    fn reload(&self, config: &Config) {
+        match load(&config.templates) {
+            Ok(registry) => *self.registry.write().unwrap() = registry,
+            Err(e) => error!("Keeping the current templates: {}", e),
+        }
+    }
+}
+
+fn template_name(locale: Locale, name: &str) -> String {
+    format!("{}/{}", locale.code(), name)
+}
+
+fn builtin_name(locale: Locale, name: &str) -> String {
+    format!("builtin:{}/{}", locale.code(), name)
+}
+
+fn load(config: &TemplatesConfig) -> Result<Handlebars<'static>> {
+    let mut registry = Handlebars::new();
+    // Comments are Markdown, not HTML
+    registry.register_escape_fn(handlebars::no_escape);
+    // Fail on unknown fields instead of rendering them as empty
+    registry.set_strict_mode(true);
+    registry.register_helper("join", Box::new(join));
+
+    for &(locale, name, source) in BUILTIN {
+        for registered in [template_name(locale, name), builtin_name(locale, name)] {
+            registry
+                .register_template_string(&registered, source)
+                .map_err(|e| {
+                    BotError::Internal(format!("Built-in template {}: {}", registered, e))
+                })?;
+        }
+    }
+
+    if let Some(dir) = &config.dir {
+        for &locale in Locale::ALL {
+            for &name in TEMPLATE_NAMES {
+                let path = Path::new(dir)
+                    .join(locale.code())
+                    .join(format!("{}.hbs", name));
+                if !path.exists() {
+                    continue;
+                }
+                let source = std::fs::read_to_string(&path).map_err(|e| {
+                    BotError::Config(format!("Failed to read template {}: {}", path.display(), e))
+                })?;
+                registry
+                    .register_template_string(&template_name(locale, name), source)
+                    .map_err(|e| {
+                        BotError::Config(format!("Invalid template {}: {}", path.display(), e))
+                    })?;
+                info!("Using template override {}", path.display());
+            }
+        }
+        check(&registry)?;
+    }
+
+    Ok(registry)
+}
+
+/// Render every template with its sample context so mistakes surface at load time
+fn check(registry: &Handlebars<'static>) -> Result<()> {
+    fn render<C: MessageContext>(registry: &Handlebars<'static>, locale: Locale) -> Result<()> {
+        registry
+            .render(&template_name(locale, C::TEMPLATE), &C::sample())
+            .map(drop)
+            .map_err(|e| {
+                BotError::Config(format!(
+                    "Template {}/{} does not render: {}",
+                    locale.code(),
+                    C::TEMPLATE,
+                    e
+                ))
+            })
+    }
+
+    for &locale in Locale::ALL {
+        render::<RunStarted>(registry, locale)?;
+        render::<StatusReport>(registry, locale)?;
+        render::<Completed>(registry, locale)?;
+        render::<Failed>(registry, locale)?;
+        render::<ErrorReply>(registry, locale)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builtin() -> Templates {
+        Templates::new(&TemplatesConfig::default()).unwrap()
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "crater-bot-templates-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("en")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_builtin_templates_render() {
+        let templates = builtin();
+        check(&templates.registry.read().unwrap()).unwrap();
+        for &locale in Locale::ALL {
+            assert!(!templates
+                .render(locale, &RunStarted::sample())
+                .ends_with('\n'));
+        }
+        assert_eq!(
+            templates.render(Locale::ZhCn, &StatusReport::sample()),
+            "📊 实验状态\n\n名称：`org--repo-1`\n工具链：stable vs beta\n状态：running\n模式：build-and-test"
+        );
+    }
+
+    #[test]
+    fn test_optional_fields() {
+        let templates = builtin();
+        let mut completed = Completed::sample();
+        assert_eq!(
+            templates.render(Locale::En, &completed),
+            "🎉 Experiment `org--repo-1` has completed!\n\n📊 Full report: [view](https://crater.example.com/report/org--repo-1)"
+        );
+        completed.report_url = None;
+        assert_eq!(
+            templates.render(Locale::En, &completed),
+            "🎉 Experiment `org--repo-1` has completed!"
+        );
+
+        let error = ErrorReply {
+            message: "<b>not html</b>".to_string(),
+            pointer: None,
+            correlation_id: None,
+        };
+        assert_eq!(
+            templates.render(Locale::En, &error),
+            "❌ Error: <b>not html</b>"
+        );
+    }
+
+    #[test]
+    fn test_override() {
+        let dir = temp_dir("override");
+        std::fs::write(
+            dir.join("en").join("failed.hbs"),
+            "Experiment {{experiment}} of {{project}} failed\n",
+        )
+        .unwrap();
+        let templates = Templates::new(&TemplatesConfig {
+            dir: Some(dir.display().to_string()),
+        })
+        .unwrap();
+
+        assert_eq!(
+            templates.render(Locale::En, &Failed::sample()),
+            "Experiment org--repo-1 of org/repo failed"
+        );
+        // Other locales and templates keep the built-in text
+        assert_eq!(
+            templates.render(Locale::ZhCn, &Failed::sample()),
+            "❌ 实验 `org--repo-1` 失败。"
+        );
+    }
+
+    #[test]
+    fn test_invalid_override_is_rejected() {
+        let dir = temp_dir("invalid");
+        std::fs::write(dir.join("en").join("status.hbs"), "{{#if status}}").unwrap();
+        let config = TemplatesConfig {
+            dir: Some(dir.display().to_string()),
+        };
+        assert!(matches!(Templates::new(&config), Err(BotError::Config(_))));
+
+        // Unknown fields are caught when loading, not when a comment is posted
+        std::fs::write(dir.join("en").join("status.hbs"), "{{experiment_name}}").unwrap();
+        let err = Templates::new(&config).err().unwrap();
+        assert!(err.to_string().contains("en/status"));
+    }
+}
@@ -0,0 +1,123 @@
+//! HTTPS with rustls: loading and reloading the certificate, and the optional plain
+//! HTTP listener redirecting to HTTPS
+use crate::config::TlsConfig;
+use crate::error::{BotError, Result};
+use crate::reload::modified_time;
+use axum::extract::Host;
+use axum::http::{StatusCode, Uri};
+use axum::response::{IntoResponse, Redirect};
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+/// Load the certificate and key named by `config`
+pub async fn load(config: &TlsConfig) -> Result<RustlsConfig> {
+    // rustls needs a process-wide crypto provider; only the first install succeeds
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    RustlsConfig::from_pem_file(&config.cert, &config.key)
+        .await
+        .map_err(|e| {
+            BotError::Config(format!(
+                "Cannot load TLS certificate {} and key {}: {}",
+                config.cert, config.key, e
+            ))
+        })
+}
+
+/// Reload the certificate whenever the certificate or key file changes, checking
+/// every `interval`. A failed reload keeps the current certificate, so replacing the
+/// two files one after the other is fine.
+pub fn watch(config: TlsConfig, rustls: RustlsConfig, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let modified = || {
+            (
+                modified_time(Path::new(&config.cert)),
+                modified_time(Path::new(&config.key)),
+            )
+        };
+        let mut last_modified = modified();
+        let mut interval = tokio::time::interval(interval);
+
+        loop {
+            interval.tick().await;
+            let current = modified();
+            if current == last_modified {
+                continue;
+            }
+            last_modified = current;
+
+            match rustls.reload_from_pem_file(&config.cert, &config.key).await {
+                Ok(()) => info!("TLS certificate reloaded from {}", config.cert),
+                Err(e) => error!(
+                    "TLS certificate reload failed, keeping the current certificate: {}",
+                    e
+                ),
+            }
+        }
+    })
+}
+
+/// Serve redirects to HTTPS on `port` of `ip`, pointing at `https_port`
+pub async fn spawn_redirect(
+    ip: std::net::IpAddr,
+    port: u16,
+    https_port: u16,
+) -> Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(SocketAddr::new(ip, port)).await?;
+    info!("Redirecting HTTP on port {} to HTTPS", port);
+    let router = redirect_router(https_port);
+    Ok(tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, router).await {
+            warn!("HTTP redirect listener stopped: {}", e);
+        }
+    }))
+}
+
+/// Router answering every request with a permanent redirect to the same path over HTTPS
+pub fn redirect_router(https_port: u16) -> Router {
+    Router::new().fallback(move |host: Option<Host>, uri: Uri| async move {
+        match host.and_then(|Host(host)| https_url(&host, https_port, &uri)) {
+            Some(url) => Redirect::permanent(&url).into_response(),
+            None => (StatusCode::BAD_REQUEST, "Missing or invalid Host header").into_response(),
+        }
+    })
+}
+
+/// `https://` URL of `uri` on `host`, replacing the port of the request
+fn https_url(host: &str, https_port: u16, uri: &Uri) -> Option<String> {
+    let authority: axum::http::uri::Authority = host.parse().ok()?;
+    let port = match https_port {
+        443 => String::new(),
+        port => format!(":{}", port),
+    };
+    let path = uri.path_and_query().map_or("/", |p| p.as_str());
+    Some(format!("https://{}{}{}", authority.host(), port, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_https_url() {
+        let uri: Uri = "/webhook/gitcode?x=1".parse().unwrap();
+        assert_eq!(
+            https_url("bot.example.com:80", 443, &uri).as_deref(),
+            Some("https://bot.example.com/webhook/gitcode?x=1")
+        );
+        assert_eq!(
+            https_url("bot.example.com", 8443, &uri).as_deref(),
+            Some("https://bot.example.com:8443/webhook/gitcode?x=1")
+        );
+        assert_eq!(
+            https_url("[::1]:8080", 8443, &"/".parse().unwrap()).as_deref(),
+            Some("https://[::1]:8443/")
+        );
+        assert_eq!(https_url("bad host", 443, &uri), None);
+    }
+}
@@ -1,5 +1,9 @@
 /// Utilities for experiment name management
 use crate::error::{BotError, Result};
+use crate::platforms::Thread;
+
+/// Marks merge request experiments: `user--repo-pr7` vs. `user--repo-7` for issue 7
+const MERGE_REQUEST_MARKER: &str = "pr";
 
 /// Generate an experiment name from a project path and issue ID
 /// Format: {project with / replaced by --}--{issue_id}
@@ -37,6 +41,35 @@ pub fn parse_experiment_name(experiment_name: &str) -> Result<(String, u64)> {
     Ok((project, issue_id))
 }
 
+/// Generate an experiment name for an issue or merge request.
+/// Issues use the `generate_experiment_name` format; merge requests add a `pr` marker
+/// before the number, e.g. "user/repo" + MR 7 -> "user--repo-pr7".
+pub fn generate_thread_experiment_name(project: &str, thread: Thread) -> String {
+    match thread {
+        Thread::Issue(issue_id) => generate_experiment_name(project, issue_id),
+        Thread::MergeRequest(number) => format!(
+            "{}-{}{}",
+            project.replace('/', "--"),
+            MERGE_REQUEST_MARKER,
+            number
+        ),
+    }
+}
+
+/// Parse an experiment name created by `generate_thread_experiment_name`
+pub fn parse_thread_experiment_name(experiment_name: &str) -> Result<(String, Thread)> {
+    if let Some((project, last)) = experiment_name.rsplit_once('-') {
+        if let Some(number) = last
+            .strip_prefix(MERGE_REQUEST_MARKER)
+            .and_then(|n| n.parse::<u64>().ok())
+        {
+            return Ok((project.replace("--", "/"), Thread::MergeRequest(number)));
+        }
+    }
+
+    let (project, issue_id) = parse_experiment_name(experiment_name)?;
+    Ok((project, Thread::Issue(issue_id)))
+}
 
 #[cfg(test)]
 mod tests {
@@ -101,4 +134,30 @@ mod tests {
         assert!(parse_experiment_name("invalid").is_err());
         assert!(parse_experiment_name("no-number-abc").is_err());
     }
+
+    #[test]
+    fn test_thread_experiment_name_roundtrip() {
+        let cases = vec![
+            ("user/repo", Thread::Issue(123)),
+            ("user/repo", Thread::MergeRequest(7)),
+            ("my-org/my-pr-repo", Thread::MergeRequest(42)),
+            ("my-org/pr", Thread::Issue(5)),
+        ];
+
+        for (project, thread) in cases {
+            let name = generate_thread_experiment_name(project, thread);
+            let (parsed_project, parsed_thread) = parse_thread_experiment_name(&name).unwrap();
+            assert_eq!(parsed_project, project, "Roundtrip failed for {}", name);
+            assert_eq!(parsed_thread, thread);
+        }
+
+        assert_eq!(
+            generate_thread_experiment_name("user/repo", Thread::MergeRequest(7)),
+            "user--repo-pr7"
+        );
+        assert_eq!(
+            generate_thread_experiment_name("user/repo", Thread::Issue(7)),
+            "user--repo-7"
+        );
+    }
 }
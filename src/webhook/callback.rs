@@ -1,24 +1,39 @@
+use crate::config::{BotConfig, Config};
 use crate::crater::WebhookCallback;
 use crate::error::Result;
-use crate::platforms::{gitcode::GitCodeAdapter, PlatformAdapter};
-use crate::utils::parse_experiment_name;
-use std::sync::Arc;
-use tracing::info;
+use crate::i18n;
+use crate::platforms::{gitcode::GitCodeAdapter, PlatformAdapter, Thread};
+use crate::reload::Reloadable;
+use crate::templates::{Completed, Failed, Templates};
+use crate::utils::parse_thread_experiment_name;
+use std::sync::{Arc, RwLock};
+use tracing::{info, info_span, Instrument};
 
 pub struct CallbackHandler {
     gitcode_adapter: Arc<GitCodeAdapter>,
+    /// For the reply language of each repository
+    config: RwLock<BotConfig>,
+    templates: Arc<Templates>,
 }
 
 impl CallbackHandler {
-    pub fn new(gitcode_adapter: Arc<GitCodeAdapter>) -> Self {
-        Self { gitcode_adapter }
+    pub fn new(
+        gitcode_adapter: Arc<GitCodeAdapter>,
+        config: &Config,
+        templates: Arc<Templates>,
+    ) -> Self {
+        Self {
+            gitcode_adapter,
+            config: RwLock::new(config.bot.clone()),
+            templates,
+        }
     }
 
     pub async fn handle_crater_callback(&self, callback: WebhookCallback) -> Result<()> {
         info!("Received crater callback: {:?}", callback);
 
-        // Parse the experiment name to extract project and issue_id
-        let (project, issue_id) = match parse_experiment_name(&callback.experiment) {
+        // Parse the experiment name to extract the project and issue or merge request
+        let (project, thread) = match parse_thread_experiment_name(&callback.experiment) {
             Ok((p, i)) => (p, i),
             Err(e) => {
                 info!("Cannot parse experiment name: {}", e);
@@ -26,32 +41,83 @@ impl CallbackHandler {
             }
         };
 
-        let message = match callback.status.as_str() {
-            "completed" => {
-                if let Some(report_url) = callback.report_url {
-                    format!(
-                        "🎉 实验 `{}` 已完成！\n\n📊 查看完整报告：[点击查看]({})",
-                        callback.experiment, report_url
-                    )
-                } else {
-                    format!("🎉 实验 `{}` 已完成！", callback.experiment)
-                }
-            }
-            "failed" => {
-                format!("❌ 实验 `{}` 失败。", callback.experiment)
-            }
-            "aborted" => {
-                format!("⏹️ 实验 `{}` 已中止。", callback.experiment)
-            }
-            status => {
-                format!("📊 实验 `{}` 状态更新：{}", callback.experiment, status)
+        let span = info_span!(
+            "callback",
+            platform = "gitcode",
+            project = %project,
+            issue = %thread,
+            experiment = %callback.experiment,
+        );
+        self.announce(&project, thread, callback)
+            .instrument(span)
+            .await
+    }
+
+    /// Post the new status of the experiment to its issue or merge request
+    async fn announce(
+        &self,
+        project: &str,
+        thread: Thread,
+        callback: WebhookCallback,
+    ) -> Result<()> {
+        // Link this callback's logs to the request that started the experiment
+        if let Ok(Some(mapping)) = self
+            .gitcode_adapter
+            .get_experiment_mapping(project, thread)
+            .await
+        {
+            if mapping.experiment == callback.experiment {
+                info!(
+                    "Experiment {} was started by request {}",
+                    mapping.experiment,
+                    mapping.request_id.as_deref().unwrap_or("unknown")
+                );
             }
-        };
+        }
 
+        let message = self.message(project, callback);
         self.gitcode_adapter
-            .post_comment(&project, issue_id, &message)
+            .post_comment(project, thread, &message)
             .await?;
 
         Ok(())
     }
+
+    /// Comment announcing the new status, in the repository's language
+    fn message(&self, project: &str, callback: WebhookCallback) -> String {
+        let locale = self.config.read().unwrap().locale_for(project);
+        match callback.status.as_str() {
+            "completed" => self.templates.render(
+                locale,
+                &Completed {
+                    experiment: callback.experiment,
+                    report_url: callback.report_url,
+                    project: project.to_string(),
+                },
+            ),
+            "failed" => self.templates.render(
+                locale,
+                &Failed {
+                    experiment: callback.experiment,
+                    project: project.to_string(),
+                },
+            ),
+            "aborted" => i18n::format(
+                locale,
+                "callback.aborted",
+                &[("name", &callback.experiment)],
+            ),
+            status => i18n::format(
+                locale,
+                "callback.status",
+                &[("name", &callback.experiment), ("status", &status)],
+            ),
+        }
+    }
+}
+
+impl Reloadable for CallbackHandler {
+    fn reload(&self, config: &Config) {
+        *self.config.write().unwrap() = config.bot.clone();
+    }
 }
@@ -1,19 +1,40 @@
+use crate::audit::{AuditEntry, AuditLog, Decision};
+use crate::bot::processor::CommandContext;
 use crate::bot::{BotCommand, CommandProcessor};
 use crate::config::Config;
 use crate::error::{BotError, Result};
-use crate::platforms::{gitcode::GitCodeAdapter, PlatformAdapter};
+use crate::i18n::{self, Locale};
+use crate::platforms::{gitcode::GitCodeAdapter, PlatformAdapter, Thread};
+use crate::reload::Reloadable;
+use crate::request_id;
+use crate::templates::{ErrorReply, Templates};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use tracing::{error, info, warn};
+use std::sync::{Arc, RwLock};
+use tracing::{error, field, info, info_span, warn, Instrument};
+
+/// Commands beyond this many in one comment are not executed
+const MAX_COMMANDS_PER_COMMENT: usize = 10;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GitCodeWebhook {
     pub object_kind: String,
+    pub user: Option<GitCodeUser>,
     pub project: Option<GitCodeProject>,
     pub issue: Option<GitCodeIssue>,
+    pub merge_request: Option<GitCodeMergeRequest>,
     pub object_attributes: Option<GitCodeNote>,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+struct GitCodeEventKind {
+    object_kind: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GitCodeUser {
+    pub username: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GitCodeProject {
     pub path_with_namespace: String,
@@ -24,6 +45,11 @@ pub struct GitCodeIssue {
     pub iid: u64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GitCodeMergeRequest {
+    pub iid: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GitCodeNote {
     pub note: String,
@@ -32,7 +58,9 @@ pub struct GitCodeNote {
 pub struct WebhookReceiver {
     processor: Arc<CommandProcessor>,
     gitcode_adapter: Arc<GitCodeAdapter>,
-    config: Config,
+    config: RwLock<Config>,
+    audit_log: Arc<AuditLog>,
+    templates: Arc<Templates>,
 }
 
 impl WebhookReceiver {
@@ -40,11 +68,15 @@ impl WebhookReceiver {
         processor: Arc<CommandProcessor>,
         gitcode_adapter: Arc<GitCodeAdapter>,
         config: Config,
+        audit_log: Arc<AuditLog>,
+        templates: Arc<Templates>,
     ) -> Self {
         Self {
             processor,
             gitcode_adapter,
-            config,
+            config: RwLock::new(config),
+            audit_log,
+            templates,
         }
     }
 
@@ -61,79 +93,210 @@ impl WebhookReceiver {
             ));
         }
 
-        // Deserialize the payload after successful verification
-        let webhook: GitCodeWebhook = serde_json::from_slice(payload)?;
-        info!("Received GitCode webhook: {:?}", webhook.object_kind);
+        // Other event kinds reuse field names with different shapes (e.g. issue events
+        // have `object_attributes` without `note`), so check the kind before the full parse
+        let kind: GitCodeEventKind = serde_json::from_slice(payload)?;
+        info!("Received GitCode webhook: {:?}", kind.object_kind);
 
         // Only process note (comment) events
-        if webhook.object_kind != "note" {
+        if kind.object_kind != "note" {
             info!("Ignoring non-note webhook event");
             return Ok(());
         }
 
+        // Deserialize the payload after successful verification
+        let webhook: GitCodeWebhook = serde_json::from_slice(payload)?;
+
         let project = webhook
             .project
             .ok_or_else(|| BotError::Platform("Missing project information".to_string()))?;
 
-        let issue = webhook
-            .issue
-            .ok_or_else(|| BotError::Platform("Missing issue information".to_string()))?;
+        // Notes on merge requests carry `merge_request` instead of `issue`
+        let thread = match (&webhook.issue, &webhook.merge_request) {
+            (Some(issue), _) => Thread::Issue(issue.iid),
+            (None, Some(merge_request)) => Thread::MergeRequest(merge_request.iid),
+            (None, None) => {
+                return Err(BotError::Platform(
+                    "Missing issue or merge request information".to_string(),
+                ))
+            }
+        };
 
         let note = webhook
             .object_attributes
             .ok_or_else(|| BotError::Platform("Missing note information".to_string()))?;
 
+        let author = webhook
+            .user
+            .map(|user| user.username)
+            .unwrap_or_else(|| "unknown".to_string());
+
+        let span = info_span!(
+            "webhook",
+            platform = "gitcode",
+            project = %project.path_with_namespace,
+            issue = %thread,
+        );
+        self.handle_comment(&project.path_with_namespace, thread, &author, &note.note)
+            .instrument(span)
+            .await
+    }
+
+    /// Run the commands in a comment on `thread` and answer them
+    async fn handle_comment(
+        &self,
+        project: &str,
+        thread: Thread,
+        author: &str,
+        note: &str,
+    ) -> Result<()> {
+        // The bot's own replies quote commands (e.g. the help text), and other bots
+        // may echo them back, so comments from these accounts are never commands
+        let ignored = self
+            .config
+            .read()
+            .unwrap()
+            .platforms
+            .gitcode
+            .as_ref()
+            .is_some_and(|gitcode| gitcode.is_ignored_author(author));
+        if ignored {
+            info!(
+                "Ignoring comment by bot account {} on {}{}",
+                author, project, thread
+            );
+            return Ok(());
+        }
+
         info!(
-            "Processing comment on {}/issue#{}",
-            project.path_with_namespace, issue.iid
+            "Processing comment by {} on {}{}",
+            author, project, thread
         );
 
-        // Parse the command
-        let command = match BotCommand::parse(&note.note, &self.config.bot.trigger_prefix)? {
-            Some(cmd) => cmd,
-            None => {
-                info!("Comment does not contain a bot command");
-                return Ok(());
-            }
+        // Parse every command in the comment
+        let (trigger_prefix, repo_locale) = {
+            let config = self.config.read().unwrap();
+            (
+                config.bot.trigger_prefix.clone(),
+                config.bot.locale_for(project),
+            )
         };
+        let mut commands = BotCommand::parse_invocations(note, &trigger_prefix);
+        if commands.is_empty() {
+            info!("Comment does not contain a bot command");
+            return Ok(());
+        }
+        let skipped = commands.len().saturating_sub(MAX_COMMANDS_PER_COMMENT);
+        commands.truncate(MAX_COMMANDS_PER_COMMENT);
 
-        info!("Parsed command: {:?}", command);
+        // Run the commands in order and answer with a single comment
+        let mut replies = Vec::new();
+        let mut failed = 0;
+        for invocation in commands {
+            // A command's `lang=` applies to its own reply only
+            let locale = invocation.lang.unwrap_or(repo_locale);
+            let mut audit = AuditEntry::new("gitcode", project, thread, author, note);
+            let span = info_span!("command", command = field::Empty, experiment = field::Empty);
 
-        // Process the command
-        let response = self
-            .processor
-            .process(
-                command,
-                self.gitcode_adapter.as_ref(),
-                &project.path_with_namespace,
-                issue.iid,
-            )
-            .await;
+            let response = match invocation.command {
+                Ok(command) => {
+                    span.record("command", command.name());
+                    span.in_scope(|| info!("Parsed command: {:?}", command));
+                    audit.command = Some(format!("{:?}", command));
+                    self.processor
+                        .process(
+                            command,
+                            self.gitcode_adapter.as_ref(),
+                            CommandContext {
+                                project,
+                                thread,
+                                author,
+                                locale,
+                            },
+                            &mut audit,
+                        )
+                        .instrument(span.clone())
+                        .await
+                }
+                Err(e) => {
+                    audit.decision = Decision::Rejected;
+                    Err(e)
+                }
+            };
 
-        match response {
-            Ok(message) => {
-                self.gitcode_adapter
-                    .post_comment(&project.path_with_namespace, issue.iid, &message)
-                    .await?;
-                info!("Command processed successfully");
-            }
-            Err(e) => {
-                error!("Error processing command: {}", e);
-                let error_message = format!("❌ Error: {}", e);
-                if let Err(comment_err) = self
-                    .gitcode_adapter
-                    .post_comment(&project.path_with_namespace, issue.iid, &error_message)
-                    .await
-                {
-                    error!("Failed to post error comment: {}", comment_err);
-                    // Also print to stderr for visibility
-                    eprintln!("Critical: Failed to post error comment: {}", comment_err);
+            audit.success = response.is_ok();
+            match response {
+                Ok(message) => replies.push(message),
+                Err(e) => {
+                    // Only user errors are shown in full; the details of others stay
+                    // in the logs under the request ID, which the user can report
+                    let correlation_id = (!e.is_user_error())
+                        .then(|| request_id::current().unwrap_or_else(request_id::generate));
+                    span.in_scope(|| match &correlation_id {
+                        Some(id) => error!("Error processing command [{}]: {:?}", id, e),
+                        None => info!("Rejected command: {}", e),
+                    });
+                    audit.error = Some(e.to_string());
+                    audit.correlation_id = correlation_id.clone();
+                    replies.push(self.error_reply(&e, locale, correlation_id));
+                    failed += 1;
                 }
-                return Err(e);
             }
+            self.audit_log.record(&audit);
+        }
+        if skipped > 0 {
+            replies.push(i18n::format(
+                repo_locale,
+                "reply.too_many_commands",
+                &[("max", &MAX_COMMANDS_PER_COMMENT), ("skipped", &skipped)],
+            ));
+        }
+
+        let reply = replies.join("\n\n---\n\n");
+        let posted = self
+            .gitcode_adapter
+            .post_comment(project, thread, &reply)
+            .await;
+
+        // Failed commands are answered in the reply. Reporting them as a failed
+        // delivery would make the platform redeliver and rerun the whole comment.
+        if let Err(e) = posted {
+            error!("Failed to post reply: {}", e);
+            return Err(e);
+        }
+        if failed == 0 {
+            info!("Comment processed successfully");
+        } else {
+            info!("Comment processed, {} command(s) failed", failed);
         }
 
         Ok(())
     }
+
+    /// Reply text for a failed command; parse errors point at the offending token
+    fn error_reply(
+        &self,
+        error: &BotError,
+        locale: Locale,
+        correlation_id: Option<String>,
+    ) -> String {
+        let pointer = match error {
+            BotError::Parse(parse_error) => Some(parse_error.pointer()),
+            _ => None,
+        };
+        self.templates.render(
+            locale,
+            &ErrorReply {
+                message: error.user_message(locale),
+                pointer,
+                correlation_id,
+            },
+        )
+    }
 }
 
+impl Reloadable for WebhookReceiver {
+    fn reload(&self, config: &Config) {
+        *self.config.write().unwrap() = config.clone();
+    }
+}
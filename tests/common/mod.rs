@@ -0,0 +1,671 @@
+//! In-process fakes of crater-ohos and the GitCode API for end-to-end tests
+#![allow(dead_code)]
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+    Json, Router,
+};
+use crater_ohos_bot::config::{
+    AuditConfig, BotConfig, Config, CraterConfig, HttpConfig, LoggingConfig, PlatformConfig,
+    PlatformsConfig, RateLimitConfig, RoutesConfig, ServerConfig, TemplatesConfig,
+};
+use crater_ohos_bot::crater::{
+    CreateExperimentRequest, Experiment, ExperimentList, ExperimentStatus, WebhookCallback,
+};
+use crater_ohos_bot::i18n::Locale;
+use crater_ohos_bot::server::App;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+pub const WEBHOOK_SECRET: &str = "test-webhook-secret";
+pub const CALLBACK_SECRET: &str = "test-callback-secret";
+pub const CRATER_TOKEN: &str = "test-crater-token";
+pub const BOT_USERNAME: &str = "crater-bot";
+
+/// Serve `router` on an ephemeral local port and return its base URL
+pub async fn spawn_router(router: Router) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    format!("http://{}", addr)
+}
+
+#[derive(Debug, Clone)]
+struct MockExperiment {
+    experiment: Experiment,
+    callback_url: Option<String>,
+}
+
+#[derive(Default)]
+struct CraterState {
+    experiments: BTreeMap<String, MockExperiment>,
+    /// Every request received, as `METHOD path`
+    requests: Vec<String>,
+    /// `X-Request-Id` of every request received
+    request_ids: Vec<Option<String>>,
+    /// `traceparent` of every request received
+    traceparents: Vec<Option<String>>,
+    /// Status code forced for the next request instead of handling it
+    fail_next: Option<StatusCode>,
+    /// Status code forced for the next run request
+    fail_run: Option<StatusCode>,
+    /// How long run requests take to answer
+    run_delay: Option<Duration>,
+}
+
+type SharedCrater = Arc<Mutex<CraterState>>;
+
+/// Fake crater-ohos implementing the `/api/v1/experiments` endpoints
+#[derive(Clone)]
+pub struct MockCrater {
+    pub url: String,
+    state: SharedCrater,
+    callback_secret: String,
+}
+
+impl MockCrater {
+    pub async fn start(callback_secret: &str) -> Self {
+        let state = SharedCrater::default();
+        let router = Router::new()
+            .route(
+                "/api/v1/experiments",
+                post(create_experiment).get(list_experiments),
+            )
+            .route(
+                "/api/v1/experiments/:name",
+                get(get_experiment).delete(delete_experiment),
+            )
+            .route("/api/v1/experiments/:name/run", post(run_experiment))
+            .route("/api/v1/experiments/:name/abort", post(abort_experiment))
+            .with_state(state.clone());
+
+        Self {
+            url: spawn_router(router).await,
+            state,
+            callback_secret: callback_secret.to_string(),
+        }
+    }
+
+    pub fn experiment(&self, name: &str) -> Option<Experiment> {
+        let state = self.state.lock().unwrap();
+        state.experiments.get(name).map(|e| e.experiment.clone())
+    }
+
+    pub fn experiments(&self) -> Vec<Experiment> {
+        let state = self.state.lock().unwrap();
+        state
+            .experiments
+            .values()
+            .map(|e| e.experiment.clone())
+            .collect()
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Insert an experiment directly, bypassing the API
+    pub fn insert(&self, experiment: Experiment) {
+        self.state.lock().unwrap().experiments.insert(
+            experiment.name.clone(),
+            MockExperiment {
+                experiment,
+                callback_url: None,
+            },
+        );
+    }
+
+    /// `X-Request-Id` headers received, in the order of [`MockCrater::requests`]
+    pub fn request_ids(&self) -> Vec<Option<String>> {
+        self.state.lock().unwrap().request_ids.clone()
+    }
+
+    /// `traceparent` headers received, in the order of [`MockCrater::requests`]
+    pub fn traceparents(&self) -> Vec<Option<String>> {
+        self.state.lock().unwrap().traceparents.clone()
+    }
+
+    /// Answer the next request with `status` instead of handling it
+    pub fn fail_next(&self, status: StatusCode) {
+        self.state.lock().unwrap().fail_next = Some(status);
+    }
+
+    /// Answer the next run request with `status` instead of starting the experiment
+    pub fn fail_run(&self, status: StatusCode) {
+        self.state.lock().unwrap().fail_run = Some(status);
+    }
+
+    /// Answer run requests only after `delay`
+    pub fn delay_run(&self, delay: Duration) {
+        self.state.lock().unwrap().run_delay = Some(delay);
+    }
+
+    /// Move an experiment to `status` without notifying anyone
+    pub fn set_status(&self, name: &str, status: ExperimentStatus) {
+        let mut state = self.state.lock().unwrap();
+        let experiment = state
+            .experiments
+            .get_mut(name)
+            .unwrap_or_else(|| panic!("unknown experiment {}", name));
+        experiment.experiment.status = status;
+    }
+
+    /// Move an experiment to `status` and POST the callback crater-ohos would send.
+    /// Returns the HTTP status the bot answered with.
+    pub async fn transition(
+        &self,
+        name: &str,
+        status: ExperimentStatus,
+        report_url: Option<&str>,
+    ) -> StatusCode {
+        let callback_url = {
+            let mut state = self.state.lock().unwrap();
+            let experiment = state
+                .experiments
+                .get_mut(name)
+                .unwrap_or_else(|| panic!("unknown experiment {}", name));
+            experiment.experiment.status = status.clone();
+            experiment.experiment.report_url = report_url.map(str::to_string);
+            experiment
+                .callback_url
+                .clone()
+                .unwrap_or_else(|| panic!("experiment {} has no callback URL", name))
+        };
+
+        let callback = WebhookCallback {
+            experiment: name.to_string(),
+            status: serde_json::to_value(&status)
+                .unwrap()
+                .as_str()
+                .unwrap()
+                .to_string(),
+            report_url: report_url.map(str::to_string),
+        };
+
+        let response = reqwest::Client::new()
+            .post(callback_url)
+            .bearer_auth(&self.callback_secret)
+            .json(&callback)
+            .send()
+            .await
+            .unwrap();
+        StatusCode::from_u16(response.status().as_u16()).unwrap()
+    }
+}
+
+/// Record the request and check auth; returns an error response if the request should fail
+fn begin(
+    state: &SharedCrater,
+    headers: &HeaderMap,
+    request: String,
+) -> Result<(), (StatusCode, String)> {
+    let mut state = state.lock().unwrap();
+    state.requests.push(request);
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|h| h.to_str().ok())
+            .map(str::to_string)
+    };
+    state.request_ids.push(header("X-Request-Id"));
+    state.traceparents.push(header("traceparent"));
+
+    if let Some(status) = state.fail_next.take() {
+        return Err((status, "scripted failure".to_string()));
+    }
+
+    let expected = format!("Bearer {}", CRATER_TOKEN);
+    if headers.get("Authorization").and_then(|h| h.to_str().ok()) != Some(expected.as_str()) {
+        return Err((StatusCode::UNAUTHORIZED, "bad token".to_string()));
+    }
+    Ok(())
+}
+
+type ApiResult<T> = Result<T, (StatusCode, String)>;
+
+async fn create_experiment(
+    State(state): State<SharedCrater>,
+    headers: HeaderMap,
+    Json(req): Json<CreateExperimentRequest>,
+) -> ApiResult<Json<Experiment>> {
+    begin(
+        &state,
+        &headers,
+        format!("POST /api/v1/experiments {}", req.name),
+    )?;
+
+    let mut state = state.lock().unwrap();
+    if state.experiments.contains_key(&req.name) {
+        return Err((StatusCode::CONFLICT, "experiment exists".to_string()));
+    }
+    let experiment = Experiment {
+        name: req.name.clone(),
+        toolchains: req.toolchains,
+        mode: req.mode,
+        crate_select: req.crate_select,
+        priority: req.priority,
+        status: ExperimentStatus::Queued,
+        report_url: None,
+    };
+    state.experiments.insert(
+        req.name,
+        MockExperiment {
+            experiment: experiment.clone(),
+            callback_url: req.callback_url,
+        },
+    );
+    Ok(Json(experiment))
+}
+
+async fn list_experiments(
+    State(state): State<SharedCrater>,
+    headers: HeaderMap,
+) -> ApiResult<Json<ExperimentList>> {
+    begin(&state, &headers, "GET /api/v1/experiments".to_string())?;
+
+    let state = state.lock().unwrap();
+    Ok(Json(ExperimentList {
+        experiments: state
+            .experiments
+            .values()
+            .map(|e| e.experiment.clone())
+            .collect(),
+    }))
+}
+
+fn with_experiment<T>(
+    state: &SharedCrater,
+    name: &str,
+    f: impl FnOnce(&mut BTreeMap<String, MockExperiment>) -> T,
+) -> ApiResult<T> {
+    let mut state = state.lock().unwrap();
+    if !state.experiments.contains_key(name) {
+        return Err((StatusCode::NOT_FOUND, "no such experiment".to_string()));
+    }
+    Ok(f(&mut state.experiments))
+}
+
+async fn get_experiment(
+    State(state): State<SharedCrater>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> ApiResult<Json<Experiment>> {
+    begin(
+        &state,
+        &headers,
+        format!("GET /api/v1/experiments/{}", name),
+    )?;
+    with_experiment(&state, &name, |experiments| {
+        Json(experiments[&name].experiment.clone())
+    })
+}
+
+async fn delete_experiment(
+    State(state): State<SharedCrater>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> ApiResult<StatusCode> {
+    begin(
+        &state,
+        &headers,
+        format!("DELETE /api/v1/experiments/{}", name),
+    )?;
+    with_experiment(&state, &name, |experiments| {
+        experiments.remove(&name);
+        StatusCode::NO_CONTENT
+    })
+}
+
+async fn run_experiment(
+    State(state): State<SharedCrater>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> ApiResult<StatusCode> {
+    begin(
+        &state,
+        &headers,
+        format!("POST /api/v1/experiments/{}/run", name),
+    )?;
+    let delay = state.lock().unwrap().run_delay;
+    if let Some(delay) = delay {
+        tokio::time::sleep(delay).await;
+    }
+    if let Some(status) = state.lock().unwrap().fail_run.take() {
+        return Err((status, "scripted failure".to_string()));
+    }
+    with_experiment(&state, &name, |experiments| {
+        experiments.get_mut(&name).unwrap().experiment.status = ExperimentStatus::Running;
+        StatusCode::OK
+    })
+}
+
+async fn abort_experiment(
+    State(state): State<SharedCrater>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> ApiResult<StatusCode> {
+    begin(
+        &state,
+        &headers,
+        format!("POST /api/v1/experiments/{}/abort", name),
+    )?;
+    with_experiment(&state, &name, |experiments| {
+        experiments.get_mut(&name).unwrap().experiment.status = ExperimentStatus::Aborted;
+        StatusCode::OK
+    })
+}
+
+/// A comment posted through the platform API
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PostedComment {
+    pub project: String,
+    /// Issue or merge request number
+    pub issue_id: u64,
+    pub merge_request: bool,
+    pub body: String,
+}
+
+/// Fake GitCode API recording posted comments and serving scripted merge requests
+#[derive(Clone)]
+pub struct MockPlatform {
+    pub url: String,
+    state: PlatformState,
+}
+
+#[derive(Clone, Default)]
+struct PlatformState {
+    comments: Arc<Mutex<Vec<PostedComment>>>,
+    /// Merge request bodies keyed by `owner/repo!number`
+    pulls: Arc<Mutex<BTreeMap<String, serde_json::Value>>>,
+}
+
+#[derive(serde::Deserialize)]
+struct CommentBody {
+    body: String,
+}
+
+impl MockPlatform {
+    pub async fn start() -> Self {
+        let state = PlatformState::default();
+        let router = Router::new()
+            .route(
+                "/repos/:owner/:repo/issues/:number/comments",
+                post(post_issue_comment),
+            )
+            .route(
+                "/repos/:owner/:repo/pulls/:number/comments",
+                post(post_pull_comment),
+            )
+            .route("/repos/:owner/:repo/pulls/:number", get(get_pull))
+            .with_state(state.clone());
+
+        Self {
+            url: spawn_router(router).await,
+            state,
+        }
+    }
+
+    pub fn comments(&self) -> Vec<PostedComment> {
+        self.state.comments.lock().unwrap().clone()
+    }
+
+    /// Serve a merge request with the given commits from `GET /repos/{project}/pulls/{number}`
+    pub fn set_merge_request(
+        &self,
+        project: &str,
+        number: u64,
+        base_sha: &str,
+        head_sha: &str,
+        merge_commit_sha: Option<&str>,
+    ) {
+        self.state.pulls.lock().unwrap().insert(
+            format!("{}!{}", project, number),
+            serde_json::json!({
+                "number": number,
+                "state": "open",
+                "head": { "ref": "feature", "sha": head_sha },
+                "base": { "ref": "master", "sha": base_sha },
+                "merge_commit_sha": merge_commit_sha,
+            }),
+        );
+    }
+
+    /// Wait until at least `count` comments have been posted
+    pub async fn wait_for_comments(&self, count: usize) -> Vec<PostedComment> {
+        for _ in 0..100 {
+            let comments = self.comments();
+            if comments.len() >= count {
+                return comments;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("expected {} comments, got {:?}", count, self.comments());
+    }
+}
+
+async fn post_issue_comment(
+    State(state): State<PlatformState>,
+    Path((owner, repo, number)): Path<(String, String, u64)>,
+    Json(body): Json<CommentBody>,
+) -> StatusCode {
+    record_comment(&state, owner, repo, number, false, body)
+}
+
+async fn post_pull_comment(
+    State(state): State<PlatformState>,
+    Path((owner, repo, number)): Path<(String, String, u64)>,
+    Json(body): Json<CommentBody>,
+) -> StatusCode {
+    record_comment(&state, owner, repo, number, true, body)
+}
+
+fn record_comment(
+    state: &PlatformState,
+    owner: String,
+    repo: String,
+    number: u64,
+    merge_request: bool,
+    body: CommentBody,
+) -> StatusCode {
+    state.comments.lock().unwrap().push(PostedComment {
+        project: format!("{}/{}", owner, repo),
+        issue_id: number,
+        merge_request,
+        body: body.body,
+    });
+    StatusCode::CREATED
+}
+
+async fn get_pull(
+    State(state): State<PlatformState>,
+    Path((owner, repo, number)): Path<(String, String, u64)>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    state
+        .pulls
+        .lock()
+        .unwrap()
+        .get(&format!("{}/{}!{}", owner, repo, number))
+        .cloned()
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Bot configuration pointing at the given fakes
+pub fn test_config(crater_url: &str, platform_url: &str, bot_url: &str) -> Config {
+    Config {
+        server: ServerConfig {
+            host: "127.0.0.1".to_string(),
+            port: 0,
+            shutdown_timeout_secs: 1,
+            tls: None,
+            routes: RoutesConfig::default(),
+            trusted_proxies: Vec::new(),
+        },
+        crater: CraterConfig {
+            api_url: crater_url.to_string(),
+            api_token: CRATER_TOKEN.to_string(),
+            api_token_file: None,
+            callback_base_url: bot_url.to_string(),
+            callback_secret: CALLBACK_SECRET.to_string(),
+            callback_secret_file: None,
+        },
+        platforms: PlatformsConfig {
+            gitcode: Some(PlatformConfig {
+                enabled: true,
+                api_url: platform_url.to_string(),
+                access_token: "test-access-token".to_string(),
+                access_token_file: None,
+                webhook_secret: WEBHOOK_SECRET.to_string(),
+                webhook_secret_file: None,
+                bot_username: Some(BOT_USERNAME.to_string()),
+                ignored_users: vec!["ci-bot".to_string()],
+                mapping_store: None,
+            }),
+            github: None,
+            gitee: None,
+        },
+        bot: BotConfig {
+            name: "crater-bot".to_string(),
+            trigger_prefix: "@crater-bot".to_string(),
+            default_mode: "build-and-test".to_string(),
+            default_crate_select: "demo".to_string(),
+            pr_base_toolchain: "master".to_string(),
+            pr_toolchain: "pr-{number}".to_string(),
+            language: Locale::ZhCn,
+            repo_languages: BTreeMap::new(),
+            allow_full_crates: false,
+        },
+        rate_limit: RateLimitConfig::default(),
+        audit: AuditConfig::default(),
+        templates: TemplatesConfig::default(),
+        logging: LoggingConfig::default(),
+        http: HttpConfig::default(),
+    }
+}
+
+/// A running bot wired to a fake crater-ohos and a fake GitCode
+pub struct TestBot {
+    pub url: String,
+    pub crater: MockCrater,
+    pub platform: MockPlatform,
+    client: reqwest::Client,
+}
+
+impl TestBot {
+    pub async fn start() -> Self {
+        Self::start_with(|_| {}).await
+    }
+
+    /// Start with a config adjusted by `configure`
+    pub async fn start_with(configure: impl FnOnce(&mut Config)) -> Self {
+        let crater = MockCrater::start(CALLBACK_SECRET).await;
+        let platform = MockPlatform::start().await;
+
+        // The bot's URL must be known before the config is built
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let mut config = test_config(&crater.url, &platform.url, &url);
+        configure(&mut config);
+        let app = App::build(&config, "unused").unwrap();
+        let service = app
+            .router
+            .into_make_service_with_connect_info::<std::net::SocketAddr>();
+        tokio::spawn(async move { axum::serve(listener, service).await });
+
+        Self {
+            url,
+            crater,
+            platform,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Send a GitCode webhook with a valid token
+    pub async fn send_webhook(&self, payload: &serde_json::Value) -> reqwest::Response {
+        self.send_raw_webhook(&serde_json::to_vec(payload).unwrap(), WEBHOOK_SECRET)
+            .await
+    }
+
+    pub async fn send_raw_webhook(&self, body: &[u8], token: &str) -> reqwest::Response {
+        self.post(
+            "/webhook/gitcode",
+            &[("X-GitCode-Token", token)],
+            body.to_vec(),
+        )
+        .await
+    }
+
+    /// GET `path` on the bot
+    pub async fn get(&self, path: &str) -> reqwest::Response {
+        self.client
+            .get(format!("{}{}", self.url, path))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    /// POST a body to `path` on the bot with extra headers, as JSON unless they set
+    /// a `Content-Type`
+    pub async fn post(
+        &self,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> reqwest::Response {
+        let mut request = self.client.post(format!("{}{}", self.url, path));
+        if !headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("Content-Type"))
+        {
+            request = request.header("Content-Type", "application/json");
+        }
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.body(body).send().await.unwrap()
+    }
+
+    /// Send a comment on an issue as `author`
+    pub async fn comment(
+        &self,
+        project: &str,
+        issue_id: u64,
+        author: &str,
+        body: &str,
+    ) -> reqwest::Response {
+        self.send_webhook(&note_payload(project, issue_id, author, body))
+            .await
+    }
+}
+
+/// A GitCode merge request note webhook body
+pub fn merge_request_note_payload(
+    project: &str,
+    number: u64,
+    author: &str,
+    body: &str,
+) -> serde_json::Value {
+    serde_json::json!({
+        "object_kind": "note",
+        "user": { "username": author },
+        "project": { "path_with_namespace": project },
+        "merge_request": { "iid": number },
+        "object_attributes": { "note": body, "noteable_type": "MergeRequest" }
+    })
+}
+
+/// A GitCode issue note webhook body
+pub fn note_payload(project: &str, issue_id: u64, author: &str, body: &str) -> serde_json::Value {
+    serde_json::json!({
+        "object_kind": "note",
+        "user": { "username": author },
+        "project": { "path_with_namespace": project },
+        "issue": { "iid": issue_id },
+        "object_attributes": { "note": body }
+    })
+}
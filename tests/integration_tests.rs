@@ -1,12 +1,697 @@
-// Integration tests can be added here in the future
-// These would test the full workflow of the bot with mock servers
-
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn placeholder_test() {
-        // This is a placeholder to ensure the test file compiles
-        let value = true;
-        assert!(value);
+//! End-to-end tests of the webhook → crater-ohos → callback → comment flow
+//! against in-process fakes of crater-ohos and the GitCode API.
+
+mod common;
+
+use axum::http::StatusCode;
+use common::TestBot;
+use crater_ohos_bot::crater::ExperimentStatus;
+use crater_ohos_bot::i18n::Locale;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn test_run_and_completion_callback() {
+    let bot = TestBot::start().await;
+
+    let response = bot
+        .comment("org/repo", 42, "alice", "@crater-bot run stable beta")
+        .await;
+    assert!(response.status().is_success());
+
+    let experiment = bot.crater.experiment("org--repo-42").unwrap();
+    assert_eq!(experiment.toolchains, vec!["stable", "beta"]);
+    assert_eq!(experiment.mode, "build-and-test");
+    assert_eq!(experiment.status, ExperimentStatus::Running);
+    assert_eq!(
+        bot.crater.requests(),
+        vec![
+            "POST /api/v1/experiments org--repo-42",
+            "POST /api/v1/experiments/org--repo-42/run",
+        ]
+    );
+
+    let comments = bot.platform.wait_for_comments(1).await;
+    assert_eq!(comments[0].project, "org/repo");
+    assert_eq!(comments[0].issue_id, 42);
+    assert!(comments[0].body.contains("org--repo-42"));
+
+    let status = bot
+        .crater
+        .transition(
+            "org--repo-42",
+            ExperimentStatus::Completed,
+            Some("https://reports.example.com/org--repo-42"),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let comments = bot.platform.wait_for_comments(2).await;
+    assert_eq!(comments[1].issue_id, 42);
+    assert!(comments[1]
+        .body
+        .contains("https://reports.example.com/org--repo-42"));
+}
+
+#[tokio::test]
+async fn test_status_and_abort() {
+    let bot = TestBot::start().await;
+
+    bot.comment("org/repo", 7, "alice", "@crater-bot status")
+        .await;
+    let comments = bot.platform.wait_for_comments(1).await;
+    assert!(comments[0].body.contains("没有"));
+
+    bot.comment("org/repo", 7, "alice", "@crater-bot run stable beta")
+        .await;
+    bot.comment("org/repo", 7, "bob", "@crater-bot status")
+        .await;
+    let comments = bot.platform.wait_for_comments(3).await;
+    assert!(comments[2].body.contains("运行中"));
+
+    bot.comment("org/repo", 7, "bob", "@crater-bot abort").await;
+    bot.platform.wait_for_comments(4).await;
+    assert_eq!(
+        bot.crater.experiment("org--repo-7").unwrap().status,
+        ExperimentStatus::Aborted
+    );
+
+    let status = bot
+        .crater
+        .transition("org--repo-7", ExperimentStatus::Aborted, None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let comments = bot.platform.wait_for_comments(5).await;
+    assert!(comments[4].body.contains("已中止"));
+}
+
+#[tokio::test]
+async fn test_crater_failure_is_reported() {
+    let bot = TestBot::start().await;
+    bot.crater.fail_next(StatusCode::INTERNAL_SERVER_ERROR);
+
+    let response = bot
+        .comment("org/repo", 1, "alice", "@crater-bot run stable beta")
+        .await;
+    // The failure is answered in the comment, so the delivery itself succeeded
+    assert!(response.status().is_success());
+
+    let comments = bot.platform.wait_for_comments(1).await;
+    let body = &comments[0].body;
+    assert!(body.starts_with("❌ 错误：crater-ohos 服务处理请求失败"));
+    // crater's response stays in the logs, the comment only carries an ID to find it by
+    assert!(!body.contains("scripted failure"));
+    assert!(!body.contains("500"));
+    assert!(body.contains("错误编号 `"));
+    assert!(bot.crater.experiment("org--repo-1").is_none());
+}
+
+#[tokio::test]
+async fn test_failed_start_can_still_be_aborted() {
+    let bot = TestBot::start().await;
+    bot.crater.fail_run(StatusCode::INTERNAL_SERVER_ERROR);
+
+    bot.comment("org/repo", 1, "alice", "@crater-bot run stable beta")
+        .await;
+    let comments = bot.platform.wait_for_comments(1).await;
+    assert!(comments[0].body.starts_with("❌"));
+    assert!(bot.crater.experiment("org--repo-1").is_some());
+
+    bot.comment("org/repo", 1, "alice", "@crater-bot abort")
+        .await;
+    bot.platform.wait_for_comments(2).await;
+    assert_eq!(
+        bot.crater.experiment("org--repo-1").unwrap().status,
+        ExperimentStatus::Aborted
+    );
+}
+
+#[tokio::test]
+async fn test_unposted_reply_fails_delivery() {
+    let bot = TestBot::start_with(|config| {
+        // Nothing listens there, so the reply cannot be posted
+        config.platforms.gitcode.as_mut().unwrap().api_url = "http://127.0.0.1:1".to_string();
+    })
+    .await;
+
+    let response = bot.comment("org/repo", 1, "alice", "@crater-bot list").await;
+    assert!(response.status().is_server_error());
+}
+
+#[tokio::test]
+async fn test_invalid_webhook_token_is_rejected() {
+    let bot = TestBot::start().await;
+
+    let payload = common::note_payload("org/repo", 1, "alice", "@crater-bot run stable beta");
+    let response = bot
+        .send_raw_webhook(&serde_json::to_vec(&payload).unwrap(), "wrong-token")
+        .await;
+    assert!(!response.status().is_success());
+
+    assert!(bot.crater.requests().is_empty());
+    assert!(bot.platform.comments().is_empty());
+}
+
+#[tokio::test]
+async fn test_regular_comment_is_ignored() {
+    let bot = TestBot::start().await;
+
+    let response = bot
+        .comment("org/repo", 1, "alice", "Looks good to me")
+        .await;
+    assert!(response.status().is_success());
+
+    assert!(bot.crater.requests().is_empty());
+    assert!(bot.platform.comments().is_empty());
+}
+
+#[tokio::test]
+async fn test_running_experiment_cap() {
+    let bot = TestBot::start_with(|config| {
+        config.rate_limit.max_running_per_project = Some(1);
+    })
+    .await;
+
+    bot.comment("org/repo", 1, "alice", "@crater-bot run stable beta")
+        .await;
+    bot.comment("org/repo", 2, "alice", "@crater-bot run stable beta")
+        .await;
+
+    let comments = bot.platform.wait_for_comments(2).await;
+    assert!(comments[1].body.starts_with("⏳"));
+    assert!(bot.crater.experiment("org--repo-2").is_none());
+}
+
+#[tokio::test]
+async fn test_run_pr_on_merge_request() {
+    let bot = TestBot::start().await;
+
+    let payload = common::merge_request_note_payload("org/repo", 7, "alice", "@crater-bot run pr");
+    let response = bot.send_webhook(&payload).await;
+    assert!(response.status().is_success());
+
+    let experiment = bot.crater.experiment("org--repo-pr7").unwrap();
+    assert_eq!(experiment.toolchains, vec!["master", "pr-7"]);
+
+    let comments = bot.platform.wait_for_comments(1).await;
+    assert!(comments[0].merge_request);
+    assert_eq!(comments[0].issue_id, 7);
+
+    // Issue 7 and merge request 7 do not share experiments
+    bot.comment("org/repo", 7, "alice", "@crater-bot status")
+        .await;
+    let comments = bot.platform.wait_for_comments(2).await;
+    assert!(!comments[1].merge_request);
+    assert!(comments[1].body.contains("没有"));
+
+    bot.crater
+        .transition("org--repo-pr7", ExperimentStatus::Completed, None)
+        .await;
+    let comments = bot.platform.wait_for_comments(3).await;
+    assert!(comments[2].merge_request);
+    assert_eq!(comments[2].issue_id, 7);
+}
+
+#[tokio::test]
+async fn test_run_pr_on_issue_is_rejected() {
+    let bot = TestBot::start().await;
+
+    bot.comment("org/repo", 3, "alice", "@crater-bot run pr")
+        .await;
+    let comments = bot.platform.wait_for_comments(1).await;
+    assert!(comments[0].body.starts_with("❌"));
+    assert!(bot.crater.requests().is_empty());
+}
+
+#[tokio::test]
+async fn test_try_resolves_merge_request_commits() {
+    let bot = TestBot::start().await;
+    bot.platform
+        .set_merge_request("org/repo", 9, "base111", "head222", Some("merge333"));
+
+    let payload = common::merge_request_note_payload("org/repo", 9, "alice", "@crater-bot try");
+    let response = bot.send_webhook(&payload).await;
+    assert!(response.status().is_success());
+
+    let experiment = bot.crater.experiment("org--repo-pr9").unwrap();
+    assert_eq!(experiment.toolchains, vec!["master#base111", "try#merge333"]);
+    let comments = bot.platform.wait_for_comments(1).await;
+    assert!(comments[0].merge_request);
+    assert!(comments[0].body.contains("try#merge333"));
+}
+
+#[tokio::test]
+async fn test_try_without_merge_commit_uses_head() {
+    let bot = TestBot::start().await;
+    bot.platform
+        .set_merge_request("org/repo", 4, "base111", "head222", None);
+
+    let payload = common::merge_request_note_payload("org/repo", 4, "alice", "@crater-bot try");
+    bot.send_webhook(&payload).await;
+
+    let experiment = bot.crater.experiment("org--repo-pr4").unwrap();
+    assert_eq!(experiment.toolchains, vec!["master#base111", "try#head222"]);
+}
+
+#[tokio::test]
+async fn test_try_on_unknown_merge_request_is_reported() {
+    let bot = TestBot::start().await;
+
+    let payload = common::merge_request_note_payload("org/repo", 5, "alice", "@crater-bot try");
+    bot.send_webhook(&payload).await;
+
+    let comments = bot.platform.wait_for_comments(1).await;
+    assert!(comments[0].body.starts_with("❌"));
+    assert!(bot.crater.requests().is_empty());
+}
+
+#[tokio::test]
+async fn test_comments_by_bot_accounts_are_ignored() {
+    let bot = TestBot::start().await;
+
+    // The bot's own help text contains command examples
+    bot.comment("org/repo", 1, common::BOT_USERNAME, "@crater-bot run stable beta")
+        .await;
+    bot.comment("org/repo", 1, "CI-Bot", "@crater-bot run stable beta")
+        .await;
+    assert!(bot.crater.requests().is_empty());
+    assert!(bot.platform.comments().is_empty());
+
+    bot.comment("org/repo", 1, "alice", "@crater-bot run stable beta")
+        .await;
+    assert!(bot.crater.experiment("org--repo-1").is_some());
+}
+
+#[tokio::test]
+async fn test_multiple_commands_get_one_reply() {
+    let bot = TestBot::start().await;
+
+    let body = "Let's compare these.\n\
+                @crater-bot run stable beta\n\
+                ```\n@crater-bot abort\n```\n\
+                > @crater-bot abort\n\
+                @crater-bot bogus\n\
+                @crater-bot status";
+    let response = bot.comment("org/repo", 1, "alice", body).await;
+    // Failed commands are answered in the reply, not reported as a failed delivery
+    // that the platform would redeliver
+    assert!(response.status().is_success());
+
+    let experiment = bot.crater.experiment("org--repo-1").unwrap();
+    assert_eq!(experiment.status, ExperimentStatus::Running);
+
+    let comments = bot.platform.wait_for_comments(1).await;
+    assert_eq!(comments.len(), 1);
+    let sections: Vec<&str> = comments[0].body.split("\n\n---\n\n").collect();
+    assert_eq!(sections.len(), 3);
+    assert!(sections[0].starts_with("✅"));
+    assert!(sections[1].starts_with("❌"));
+    assert!(sections[2].contains("org--repo-1"));
+}
+
+#[tokio::test]
+async fn test_run_options_are_sent_to_crater() {
+    let bot = TestBot::start().await;
+
+    bot.comment(
+        "org/repo",
+        1,
+        "alice",
+        r#"@crater-bot run "nightly+rustflags=-C opt-level=3" stable --mode check-only crates="list:serde, rand""#,
+    )
+    .await;
+
+    let experiment = bot.crater.experiment("org--repo-1").unwrap();
+    assert_eq!(
+        experiment.toolchains,
+        vec!["nightly+rustflags=-C opt-level=3", "stable"]
+    );
+    assert_eq!(experiment.mode, "check-only");
+    assert_eq!(experiment.crate_select, "list:serde, rand");
+}
+
+#[tokio::test]
+async fn test_full_crates_needs_configuration() {
+    let bot = TestBot::start().await;
+    bot.comment("org/repo", 1, "alice", "@crater-bot run stable beta crates=full")
+        .await;
+
+    let comments = bot.platform.wait_for_comments(1).await;
+    assert!(comments[0].body.starts_with("❌"));
+    assert!(comments[0].body.contains("crates=full"));
+    assert!(bot.crater.requests().is_empty());
+
+    let bot = TestBot::start_with(|config| config.bot.allow_full_crates = true).await;
+    bot.comment("org/repo", 1, "alice", "@crater-bot run stable beta crates=full")
+        .await;
+
+    bot.platform.wait_for_comments(1).await;
+    let experiment = bot.crater.experiment("org--repo-1").unwrap();
+    assert_eq!(experiment.crate_select, "full");
+}
+
+#[tokio::test]
+async fn test_parse_error_points_at_token() {
+    let bot = TestBot::start().await;
+
+    bot.comment("org/repo", 1, "alice", "@crater-bot run stable beta --colour red")
+        .await;
+
+    let comments = bot.platform.wait_for_comments(1).await;
+    assert!(comments[0].body.starts_with("❌"));
+    assert!(comments[0].body.contains("（第 29 列）"));
+    assert!(comments[0]
+        .body
+        .contains("@crater-bot run stable beta --colour red\n                            ^^^^^^^^^^^^\n"));
+    assert!(bot.crater.requests().is_empty());
+}
+
+#[tokio::test]
+async fn test_help_for_command_uses_configured_defaults() {
+    let bot = TestBot::start_with(|config| {
+        config.bot.default_mode = "check-only".to_string();
+        config.bot.pr_base_toolchain = "nightly".to_string();
+    })
+    .await;
+
+    bot.comment("org/repo", 1, "alice", "@crater-bot help run pr")
+        .await;
+    let comments = bot.platform.wait_for_comments(1).await;
+    let body = &comments[0].body;
+    assert!(body.starts_with("## `run pr` 帮助"));
+    assert!(body.contains("`nightly`"));
+    assert!(body.contains("`mode=<mode>`"));
+    assert!(body.contains("（默认：`check-only`）"));
+    assert!(body.contains("@crater-bot run pr mode=check-only"));
+    assert!(bot.crater.requests().is_empty());
+}
+
+#[tokio::test]
+async fn test_lang_option_switches_reply_language() {
+    let bot = TestBot::start().await;
+
+    bot.comment(
+        "org/repo",
+        1,
+        "alice",
+        "@crater-bot status lang=en\n@crater-bot run stable --lang en\n@crater-bot status",
+    )
+    .await;
+
+    let comments = bot.platform.wait_for_comments(1).await;
+    let replies: Vec<&str> = comments[0].body.split("\n\n---\n\n").collect();
+    assert_eq!(
+        replies[0],
+        "There is no experiment associated with this issue."
+    );
+    assert!(replies[1].starts_with("❌ Error: Invalid command: run command requires"));
+    assert!(replies[1].contains("(column 13)"));
+    assert_eq!(replies[2], "当前没有与此 Issue 关联的实验。");
+}
+
+#[tokio::test]
+async fn test_repository_language_applies_to_callbacks() {
+    let bot = TestBot::start_with(|config| {
+        config
+            .bot
+            .repo_languages
+            .insert("org/english".to_string(), Locale::En);
+    })
+    .await;
+
+    bot.comment("org/english", 3, "alice", "@crater-bot run stable beta")
+        .await;
+    let comments = bot.platform.wait_for_comments(1).await;
+    assert!(comments[0]
+        .body
+        .starts_with("✅ Experiment `org--english-3` has been created"));
+
+    bot.crater
+        .transition("org--english-3", ExperimentStatus::Failed, None)
+        .await;
+    let comments = bot.platform.wait_for_comments(2).await;
+    assert_eq!(comments[1].body, "❌ Experiment `org--english-3` failed.");
+
+    // A command's own language wins over the repository's
+    bot.comment("org/english", 3, "alice", "@crater-bot status lang=zh-CN")
+        .await;
+    let comments = bot.platform.wait_for_comments(3).await;
+    assert!(comments[2].body.contains("状态：失败"));
+}
+
+#[tokio::test]
+async fn test_template_overrides_completion_comment() {
+    let dir = std::env::temp_dir().join(format!("crater-bot-it-templates-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("zh-CN")).unwrap();
+    std::fs::write(
+        dir.join("zh-CN").join("completed.hbs"),
+        "{{project}}: {{experiment}} done{{#if report_url}} -> {{report_url}}{{/if}}\n",
+    )
+    .unwrap();
+    let bot = TestBot::start_with(|config| {
+        config.templates.dir = Some(dir.display().to_string());
+    })
+    .await;
+
+    bot.comment("org/repo", 5, "alice", "@crater-bot run stable beta")
+        .await;
+    bot.platform.wait_for_comments(1).await;
+    bot.crater
+        .transition(
+            "org--repo-5",
+            ExperimentStatus::Completed,
+            Some("https://reports.example.com/5"),
+        )
+        .await;
+
+    let comments = bot.platform.wait_for_comments(2).await;
+    assert_eq!(
+        comments[1].body,
+        "org/repo: org--repo-5 done -> https://reports.example.com/5"
+    );
+    // Templates without an override keep the built-in text
+    assert!(comments[0].body.starts_with("✅ 实验 `org--repo-5` 已创建并开始执行。"));
+}
+
+#[tokio::test]
+async fn test_request_id_is_propagated() {
+    let bot = TestBot::start().await;
+    let payload = common::note_payload("org/repo", 9, "alice", "@crater-bot run stable beta");
+
+    let response = bot
+        .post(
+            "/webhook/gitcode",
+            &[("X-GitCode-Token", common::WEBHOOK_SECRET), ("X-Request-Id", "it-run-9")],
+            serde_json::to_vec(&payload).unwrap(),
+        )
+        .await;
+    assert_eq!(response.headers()["x-request-id"], "it-run-9");
+    assert_eq!(
+        bot.crater.request_ids(),
+        vec![Some("it-run-9".to_string()), Some("it-run-9".to_string())]
+    );
+
+    // Without a usable ID from the client a new one is generated
+    let response = bot
+        .post(
+            "/webhook/gitcode",
+            &[("X-GitCode-Token", common::WEBHOOK_SECRET), ("X-Request-Id", "not valid!")],
+            serde_json::to_vec(&common::note_payload("org/repo", 9, "alice", "@crater-bot status"))
+                .unwrap(),
+        )
+        .await;
+    let generated = response.headers()["x-request-id"].to_str().unwrap().to_string();
+    assert_ne!(generated, "not valid!");
+    assert_eq!(bot.crater.request_ids()[2].as_deref(), Some(generated.as_str()));
+}
+
+#[tokio::test]
+async fn test_error_comment_echoes_request_id() {
+    let bot = TestBot::start().await;
+    bot.crater.fail_next(StatusCode::BAD_GATEWAY);
+    let payload = common::note_payload("org/repo", 2, "alice", "@crater-bot list");
+
+    bot.post(
+        "/webhook/gitcode",
+        &[("X-GitCode-Token", common::WEBHOOK_SECRET), ("X-Request-Id", "it-list-2")],
+        serde_json::to_vec(&payload).unwrap(),
+    )
+    .await;
+
+    let comments = bot.platform.wait_for_comments(1).await;
+    assert!(comments[0].body.contains("错误编号 `it-list-2`"));
+}
+
+#[tokio::test]
+async fn test_hung_crater_times_out() {
+    // Accepts connections but never answers
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let hung_url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut connections = Vec::new();
+        while let Ok((connection, _)) = listener.accept().await {
+            connections.push(connection);
+        }
+    });
+
+    let bot = TestBot::start_with(|config| {
+        config.crater.api_url = hung_url;
+        config.http.timeout_secs = 1;
+    })
+    .await;
+
+    let started = Instant::now();
+    bot.comment("org/repo", 4, "alice", "@crater-bot list").await;
+    let comments = bot.platform.wait_for_comments(1).await;
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(comments[0].body.contains("暂时无法连接到所需的服务"));
+}
+
+#[tokio::test]
+async fn test_oversized_body_rejected() {
+    let bot = TestBot::start_with(|config| {
+        config.server.routes.webhook.max_body_bytes = 256;
+    })
+    .await;
+
+    let payload = common::note_payload("org/repo", 1, "alice", &"x".repeat(512));
+    let response = bot.send_webhook(&payload).await;
+    assert_eq!(response.status(), 413);
+
+    // Other routes keep their own limit
+    let response = bot.post("/callback/crater", &[], vec![b' '; 512]).await;
+    assert_ne!(response.status(), 413);
+    assert!(bot.platform.comments().is_empty());
+}
+
+#[tokio::test]
+async fn test_non_json_body_rejected() {
+    let bot = TestBot::start().await;
+    let payload = common::note_payload("org/repo", 1, "alice", "@crater-bot list");
+
+    for path in ["/webhook/gitcode", "/callback/crater"] {
+        let response = bot
+            .post(
+                path,
+                &[("Content-Type", "text/plain")],
+                serde_json::to_vec(&payload).unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), 415, "{}", path);
+    }
+
+    let response = bot
+        .post(
+            "/webhook/gitcode",
+            &[
+                ("X-GitCode-Token", common::WEBHOOK_SECRET),
+                ("Content-Type", "application/json; charset=utf-8"),
+            ],
+            serde_json::to_vec(&payload).unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn test_slow_webhook_times_out() {
+    // Accepts connections but never answers
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let hung_url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut connections = Vec::new();
+        while let Ok((connection, _)) = listener.accept().await {
+            connections.push(connection);
+        }
+    });
+
+    let bot = TestBot::start_with(|config| {
+        config.crater.api_url = hung_url;
+        config.server.routes.webhook.timeout_secs = 1;
+    })
+    .await;
+
+    let started = Instant::now();
+    let response = bot
+        .comment("org/repo", 4, "alice", "@crater-bot list")
+        .await;
+    assert_eq!(response.status(), 408);
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_timed_out_run_still_stores_mapping() {
+    let bot = TestBot::start_with(|config| {
+        config.server.routes.webhook.timeout_secs = 1;
+    })
+    .await;
+    bot.crater.delay_run(Duration::from_millis(1500));
+
+    let response = bot
+        .comment("org/repo", 1, "alice", "@crater-bot run stable beta")
+        .await;
+    assert_eq!(response.status(), 408);
+
+    // The command finishes after the response was cut short
+    let comments = bot.platform.wait_for_comments(1).await;
+    assert!(comments[0].body.contains("org--repo-1"));
+
+    bot.comment("org/repo", 1, "alice", "@crater-bot status")
+        .await;
+    let comments = bot.platform.wait_for_comments(2).await;
+    assert!(comments[1].body.contains("org--repo-1"));
+    assert!(comments[1].body.starts_with("📊"));
+}
+
+#[tokio::test]
+async fn test_source_outside_allow_list_rejected() {
+    let bot = TestBot::start_with(|config| {
+        config.server.routes.webhook.allow = vec!["192.0.2.0/24".parse().unwrap()];
+    })
+    .await;
+
+    // Rejected before the token is looked at
+    let payload = common::note_payload("org/repo", 1, "alice", "@crater-bot list");
+    let response = bot
+        .send_raw_webhook(&serde_json::to_vec(&payload).unwrap(), "wrong-token")
+        .await;
+    assert_eq!(response.status(), 403);
+    let response = bot.send_webhook(&payload).await;
+    assert_eq!(response.status(), 403);
+    assert!(bot.crater.requests().is_empty());
+
+    let metrics = bot.get("/metrics").await.text().await.unwrap();
+    assert!(metrics.contains("crater_ohos_bot_rejected_sources_total{route=\"webhook\"} 2\n"));
+}
+
+#[tokio::test]
+async fn test_allow_list_behind_trusted_proxy() {
+    let bot = TestBot::start_with(|config| {
+        config.server.trusted_proxies = vec!["127.0.0.1/32".parse().unwrap()];
+        config.server.routes.webhook.allow = vec!["203.0.113.0/24".parse().unwrap()];
+        config.server.routes.metrics.allow = vec!["10.0.0.0/8".parse().unwrap()];
+    })
+    .await;
+
+    let payload = serde_json::to_vec(&common::note_payload(
+        "org/repo",
+        1,
+        "alice",
+        "@crater-bot list",
+    ))
+    .unwrap();
+    for (forwarded_for, status) in [("203.0.113.7", 200), ("203.0.113.7, 198.51.100.1", 403)] {
+        let response = bot
+            .post(
+                "/webhook/gitcode",
+                &[
+                    ("X-GitCode-Token", common::WEBHOOK_SECRET),
+                    ("X-Forwarded-For", forwarded_for),
+                ],
+                payload.clone(),
+            )
+            .await;
+        assert_eq!(response.status(), status, "{}", forwarded_for);
     }
+    assert_eq!(bot.get("/metrics").await.status(), 403);
 }
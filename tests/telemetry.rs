@@ -0,0 +1,154 @@
+//! Trace export to a stand-in OpenTelemetry collector. The subscriber is global, so
+//! this file holds a single test.
+
+mod common;
+
+use axum::{body::Bytes, extract::State, routing::post, Router};
+use common::TestBot;
+use crater_ohos_bot::config::{LoggingConfig, OtlpConfig};
+use crater_ohos_bot::logging;
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+use opentelemetry_proto::tonic::common::v1::any_value::Value;
+use opentelemetry_proto::tonic::trace::v1::Span;
+use prost::Message;
+use std::sync::{Arc, Mutex};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+/// Accepts OTLP/HTTP exports and keeps the decoded requests
+#[derive(Clone, Default)]
+struct Collector {
+    exports: Arc<Mutex<Vec<ExportTraceServiceRequest>>>,
+}
+
+impl Collector {
+    async fn start(&self) -> String {
+        let router = Router::new()
+            .route("/v1/traces", post(receive))
+            .with_state(self.clone());
+        common::spawn_router(router).await
+    }
+
+    fn service_names(&self) -> Vec<String> {
+        let exports = self.exports.lock().unwrap();
+        exports
+            .iter()
+            .flat_map(|export| &export.resource_spans)
+            .filter_map(|spans| spans.resource.as_ref())
+            .flat_map(|resource| &resource.attributes)
+            .filter(|attribute| attribute.key == "service.name")
+            .filter_map(
+                |attribute| match attribute.value.as_ref()?.value.as_ref()? {
+                    Value::StringValue(name) => Some(name.clone()),
+                    _ => None,
+                },
+            )
+            .collect()
+    }
+
+    fn spans(&self) -> Vec<Span> {
+        let exports = self.exports.lock().unwrap();
+        exports
+            .iter()
+            .flat_map(|export| &export.resource_spans)
+            .flat_map(|spans| &spans.scope_spans)
+            .flat_map(|scope| scope.spans.clone())
+            .collect()
+    }
+}
+
+async fn receive(State(collector): State<Collector>, body: Bytes) {
+    let export = ExportTraceServiceRequest::decode(body).unwrap();
+    collector.exports.lock().unwrap().push(export);
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[test]
+fn test_spans_exported_with_trace_context() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let collector = Collector::default();
+    let collector_url = runtime.block_on(collector.start());
+
+    // Created outside the runtime, like in main
+    let guard = logging::init(&LoggingConfig {
+        otlp: Some(OtlpConfig {
+            endpoint: format!("{}/v1/traces", collector_url),
+            service_name: "bot-under-test".to_string(),
+            sample_ratio: 1.0,
+        }),
+        ..Default::default()
+    })
+    .unwrap();
+
+    let bot = runtime.block_on(async {
+        let bot = TestBot::start().await;
+        let payload = common::note_payload("org/repo", 5, "alice", "@crater-bot run stable beta");
+        let traceparent = format!("00-{}-00f067aa0ba902b7-01", TRACE_ID);
+        bot.post(
+            "/webhook/gitcode",
+            &[
+                ("X-GitCode-Token", common::WEBHOOK_SECRET),
+                ("traceparent", traceparent.as_str()),
+            ],
+            serde_json::to_vec(&payload).unwrap(),
+        )
+        .await;
+        bot
+    });
+
+    // Shutting down exports the pending spans
+    drop(guard);
+
+    assert_eq!(collector.service_names(), vec!["bot-under-test"]);
+    let spans = collector.spans();
+    let span = |name: &str| {
+        spans
+            .iter()
+            .find(|span| span.name == name)
+            .unwrap_or_else(|| panic!("no span {}", name))
+    };
+    for name in [
+        "request",
+        "webhook",
+        "command",
+        "crater.create_experiment",
+        "crater.run_experiment",
+        "gitcode.store_experiment_mapping",
+        "gitcode.post_comment",
+    ] {
+        assert_eq!(hex(&span(name).trace_id), TRACE_ID, "{}", name);
+    }
+
+    // The webhook continues the caller's trace and nests down to the crater-ohos calls
+    assert_eq!(hex(&span("request").parent_span_id), "00f067aa0ba902b7");
+    assert_eq!(span("webhook").parent_span_id, span("request").span_id);
+    assert_eq!(span("command").parent_span_id, span("webhook").span_id);
+    assert_eq!(
+        span("crater.create_experiment").parent_span_id,
+        span("command").span_id
+    );
+
+    // crater-ohos is told which span called it
+    let traceparents: Vec<String> = bot.crater.traceparents().into_iter().flatten().collect();
+    assert_eq!(
+        traceparents,
+        vec![
+            format!(
+                "00-{}-{}-01",
+                TRACE_ID,
+                hex(&span("crater.create_experiment").span_id)
+            ),
+            format!(
+                "00-{}-{}-01",
+                TRACE_ID,
+                hex(&span("crater.run_experiment").span_id)
+            ),
+        ]
+    );
+}
@@ -0,0 +1,118 @@
+//! HTTPS serving, certificate reloading and the HTTP→HTTPS redirect
+
+mod common;
+
+use axum::{routing::get, Router};
+use crater_ohos_bot::config::TlsConfig;
+use crater_ohos_bot::server::serve_tls_until;
+use crater_ohos_bot::tls;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// A self-signed certificate for `localhost` and its key, as PEM
+fn certificate() -> (String, String) {
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    (certified.cert.pem(), certified.key_pair.serialize_pem())
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("crater-ohos-bot-{}-{}", std::process::id(), name))
+}
+
+/// GET `url` with a client trusting only `root`
+async fn get_trusting(root: &str, url: &str) -> reqwest::Result<String> {
+    reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(root.as_bytes()).unwrap())
+        .build()
+        .unwrap()
+        .get(url)
+        .send()
+        .await?
+        .text()
+        .await
+}
+
+#[tokio::test]
+async fn test_https_with_certificate_reload() {
+    let (cert, key) = certificate();
+    let config = TlsConfig {
+        cert: temp_path("tls-cert.pem").to_string_lossy().into_owned(),
+        key: temp_path("tls-key.pem").to_string_lossy().into_owned(),
+        redirect_http_port: None,
+    };
+    std::fs::write(&config.cert, &cert).unwrap();
+    std::fs::write(&config.key, &key).unwrap();
+
+    let rustls = tls::load(&config).await.unwrap();
+    let watcher = tls::watch(config.clone(), rustls.clone(), Duration::from_millis(50));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!(
+        "https://localhost:{}/health",
+        listener.local_addr().unwrap().port()
+    );
+    let router = Router::new().route("/health", get(|| async { "OK" }));
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(serve_tls_until(
+        listener,
+        router,
+        rustls,
+        async move {
+            let _ = stopped.await;
+        },
+        Duration::from_secs(1),
+    ));
+
+    assert_eq!(get_trusting(&cert, &url).await.unwrap(), "OK");
+
+    // A renewed certificate is picked up without a restart
+    let (new_cert, new_key) = certificate();
+    std::fs::write(&config.key, &new_key).unwrap();
+    std::fs::write(&config.cert, &new_cert).unwrap();
+    let mut reloaded = false;
+    for _ in 0..50 {
+        if get_trusting(&new_cert, &url).await.is_ok() {
+            reloaded = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(reloaded, "renewed certificate was not served");
+    assert!(get_trusting(&cert, &url).await.is_err());
+
+    let _ = stop.send(());
+    server.await.unwrap().unwrap();
+    watcher.abort();
+}
+
+#[tokio::test]
+async fn test_invalid_certificate() {
+    let config = TlsConfig {
+        cert: temp_path("missing-cert.pem").to_string_lossy().into_owned(),
+        key: temp_path("missing-key.pem").to_string_lossy().into_owned(),
+        redirect_http_port: None,
+    };
+    let error = tls::load(&config).await.unwrap_err();
+    assert!(error.to_string().contains("missing-cert.pem"));
+}
+
+#[tokio::test]
+async fn test_http_redirects_to_https() {
+    let url = common::spawn_router(tls::redirect_router(8443)).await;
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+
+    let response = client
+        .post(format!("{}/webhook/gitcode?a=1", url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 308);
+    let host = url.trim_start_matches("http://").split(':').next().unwrap();
+    assert_eq!(
+        response.headers()["location"],
+        format!("https://{}:8443/webhook/gitcode?a=1", host).as_str()
+    );
+}
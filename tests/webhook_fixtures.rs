@@ -0,0 +1,196 @@
+//! Replays recorded webhook bodies from `tests/fixtures/webhooks/<platform>/` through the
+//! router. Each `<name>.json` body sits next to a `<name>.expect.json` describing the
+//! headers to send and the expected response, parsed event, crater-ohos requests and
+//! posted comments. To cover a newly captured payload, sanitize it and drop both files in.
+//! Only GitCode webhooks are routed so far, so only GitCode payloads are recorded.
+
+mod common;
+
+use common::{TestBot, WEBHOOK_SECRET};
+use crater_ohos_bot::webhook::GitCodeWebhook;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Expectation {
+    description: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    status: u16,
+    /// How `GitCodeWebhook` deserializes the body; omitted for events it does not
+    /// model, such as issue events
+    parsed: Option<ParsedEvent>,
+    #[serde(default)]
+    crater_requests: Vec<String>,
+    #[serde(default)]
+    comments: Vec<ExpectedComment>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct ParsedEvent {
+    object_kind: String,
+    project: Option<String>,
+    issue: Option<u64>,
+    merge_request: Option<u64>,
+    note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExpectedComment {
+    project: String,
+    issue_id: u64,
+    #[serde(default)]
+    merge_request: bool,
+    /// Substring the comment body must contain
+    contains: String,
+}
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/webhooks")
+}
+
+/// `(platform, body path, expectation path)` for every fixture, sorted
+fn fixtures() -> Vec<(String, PathBuf, PathBuf)> {
+    let mut fixtures = Vec::new();
+    for platform in std::fs::read_dir(fixtures_dir()).unwrap() {
+        let platform = platform.unwrap().path();
+        if !platform.is_dir() {
+            continue;
+        }
+        let name = platform.file_name().unwrap().to_string_lossy().into_owned();
+        for file in std::fs::read_dir(&platform).unwrap() {
+            let body = file.unwrap().path();
+            let file_name = body.file_name().unwrap().to_string_lossy().into_owned();
+            if !file_name.ends_with(".json") || file_name.ends_with(".expect.json") {
+                continue;
+            }
+            let expect = body.with_extension("expect.json");
+            assert!(
+                expect.exists(),
+                "fixture {} has no {}",
+                body.display(),
+                expect.display()
+            );
+            fixtures.push((name.clone(), body, expect));
+        }
+    }
+    fixtures.sort();
+    fixtures
+}
+
+fn parse_event(body: &[u8]) -> Result<ParsedEvent, String> {
+    let webhook: GitCodeWebhook = serde_json::from_slice(body).map_err(|e| e.to_string())?;
+    Ok(ParsedEvent {
+        object_kind: webhook.object_kind,
+        project: webhook.project.map(|p| p.path_with_namespace),
+        issue: webhook.issue.map(|i| i.iid),
+        merge_request: webhook.merge_request.map(|m| m.iid),
+        note: webhook.object_attributes.map(|n| n.note),
+    })
+}
+
+async fn check_fixture(platform: &str, body_path: &Path, expect_path: &Path) -> Vec<String> {
+    let body = std::fs::read(body_path).unwrap();
+    let expect: Expectation = serde_json::from_slice(&std::fs::read(expect_path).unwrap())
+        .unwrap_or_else(|e| panic!("invalid {}: {}", expect_path.display(), e));
+    let mut failures = Vec::new();
+
+    if let Some(parsed) = &expect.parsed {
+        match parse_event(&body) {
+            Ok(actual) if actual == *parsed => {}
+            Ok(actual) => failures.push(format!("parsed {:?}, expected {:?}", actual, parsed)),
+            Err(e) => failures.push(format!("failed to parse: {}", e)),
+        }
+    }
+
+    let bot = TestBot::start().await;
+    let mut headers: Vec<(&str, &str)> = expect
+        .headers
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    if platform == "gitcode" && !expect.headers.contains_key("X-GitCode-Token") {
+        headers.push(("X-GitCode-Token", WEBHOOK_SECRET));
+    }
+    let response = bot
+        .post(&format!("/webhook/{}", platform), &headers, body)
+        .await;
+
+    if response.status().as_u16() != expect.status {
+        failures.push(format!(
+            "status {}, expected {}",
+            response.status(),
+            expect.status
+        ));
+    }
+
+    let crater_requests = bot.crater.requests();
+    if crater_requests != expect.crater_requests {
+        failures.push(format!(
+            "crater requests {:?}, expected {:?}",
+            crater_requests, expect.crater_requests
+        ));
+    }
+
+    let comments = if expect.comments.is_empty() {
+        bot.platform.comments()
+    } else {
+        bot.platform.wait_for_comments(expect.comments.len()).await
+    };
+    if comments.len() != expect.comments.len() {
+        failures.push(format!(
+            "{} comments posted, expected {}: {:?}",
+            comments.len(),
+            expect.comments.len(),
+            comments
+        ));
+    }
+    for (actual, expected) in comments.iter().zip(&expect.comments) {
+        if actual.project != expected.project
+            || actual.issue_id != expected.issue_id
+            || actual.merge_request != expected.merge_request
+            || !actual.body.contains(&expected.contains)
+        {
+            failures.push(format!(
+                "comment {:?} does not match {:?}",
+                actual, expected
+            ));
+        }
+    }
+
+    failures
+        .into_iter()
+        .map(|f| {
+            format!(
+                "{}/{} ({}): {}",
+                platform,
+                body_path.file_name().unwrap().to_string_lossy(),
+                expect.description,
+                f
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_replay_webhook_fixtures() {
+    let fixtures = fixtures();
+    assert!(!fixtures.is_empty(), "no webhook fixtures found");
+
+    let mut failures = Vec::new();
+    for (platform, body, expect) in &fixtures {
+        failures.extend(check_fixture(platform, body, expect).await);
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} fixtures failed:\n{}",
+        failures.len(),
+        fixtures.len(),
+        failures.join("\n")
+    );
+}